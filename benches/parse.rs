@@ -0,0 +1,48 @@
+//! Compare the owned and borrowed directory parsers
+//!
+//! Run with `cargo bench --bench parse`.
+
+extern crate gopher;
+
+use std::time::{Duration, Instant};
+
+use gopher::*;
+
+const ITEMS: usize = 100_000;
+const RUNS: u32 = 10;
+
+/// Build a large menu, like a page of search results
+fn menu() -> String {
+    let mut menu = String::new();
+    for n in 0..ITEMS {
+        if n % 10 == 0 {
+            menu.push_str(&format!("iResults page {}\tfake\t(NULL)\t0\r\n", n / 10));
+        } else {
+            menu.push_str(&format!("0Search result number {}\t/archive/{}/result.txt\tgopher.example.net\t70\r\n", n, n));
+        }
+    }
+    menu.push_str(".\r\n");
+    menu
+}
+
+/// Run a parser several times, returning the average time taken
+fn time<F: Fn(&str) -> usize>(input: &str, parse: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        assert_eq!(parse(input), ITEMS);
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let input = menu();
+
+    let owned = time(&input, |s| Directory::from_str(s).unwrap().items().len());
+    let borrowed = time(&input, |s| DirectoryRef::from_str(s).unwrap().items().len());
+    let converted = time(&input, |s| DirectoryRef::from_str(s).unwrap().into_owned().items().len());
+
+    println!("{} items, average of {} runs", ITEMS, RUNS);
+    println!("Directory::from_str:               {:?}", owned);
+    println!("DirectoryRef::from_str:            {:?}", borrowed);
+    println!("DirectoryRef::from_str + owned:    {:?}", converted);
+}
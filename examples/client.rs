@@ -6,10 +6,11 @@ use gopher::net::*;
 use std::env;
 
 fn pretty_print_directory(dir: &Directory) {
-    for item in dir.items().iter() {
-        if item.is_info() {
-            println!("{}", item.name);
-        } else {
+    for section in dir.sections() {
+        for line in &section.heading {
+            println!("{}", line.name);
+        }
+        for item in &section.links {
             println!("{sym} {name:100} {host}:{port} {selector}",
                      sym=item.t.as_char(),
                      name=item.name,
@@ -23,19 +24,28 @@ fn pretty_print_directory(dir: &Directory) {
 fn main() {
     let mut args = env::args();
 
-    let host = args.nth(1).unwrap_or(String::from("gopher.quux.org:70"));
-    let selector = args.next().unwrap_or(String::from(""));
+    let url = args.nth(1).unwrap_or(String::from("gopher://gopher.quux.org/1"));
+    let url = GopherUrl::from_str(&url).expect("could not parse url");
 
-    let result = read_directory_or_resource(&*host, &selector)
-        .expect("could not read resource");
+    let item = url.to_item(&url.to_string());
+    let client = Client::new();
+    let resource = match url.search {
+        // gopher://host/7selector%09query sends a search
+        Some(ref query) => client.search(&item, query).map(Resource::SearchResults),
+        None => client.fetch(&item),
+    }.expect("could not read resource");
 
-    if let Ok(directory) = result {
-        println!("Got Directory:\n");
-        pretty_print_directory(&directory);        
-    } else {
-        if let Err(resource) = result {
+    match resource {
+        Resource::Menu(directory) | Resource::SearchResults(directory) => {
+            println!("Got Directory:\n");
+            pretty_print_directory(&directory);
+        },
+        Resource::Text(text) => {
             println!("Got Resource:\n");
-            println!("{}", resource);
-        }
+            println!("{}", text);
+        },
+        Resource::Binary(bytes) => println!("Got {} bytes of binary data", bytes.len()),
+        Resource::Error(message) => println!("Server error: {}", message),
+        Resource::ExternalUrl(url) => println!("External link to {}", url),
     }
 }
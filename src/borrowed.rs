@@ -0,0 +1,212 @@
+//! Zero-Copy Directory Parsing
+//!
+//! `DirectoryItem::from_str` allocates a `String` for every field of every
+//! item, which adds up when parsing a lot of menus.  A `DirectoryItemRef`
+//! borrows its fields from the input instead, and can be converted into an
+//! owned `DirectoryItem` only for the items which need to be kept.
+
+use std::fmt;
+
+use GopherError;
+use Position;
+use Type;
+use DirectoryItem;
+use Directory;
+
+/// An item in a Gopher Directory, borrowing its fields from the input
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DirectoryItemRef<'a> {
+    pub t: Type,
+    pub name: &'a str,
+    pub selector: &'a str,
+    pub host: &'a str,
+    pub port: u16,
+    /// Everything following the port and its tab, if there was anything,
+    /// with the fields still tab-separated
+    pub extra: Option<&'a str>,
+}
+
+impl<'a> DirectoryItemRef<'a> {
+    /// Parse a &str into a DirectoryItemRef
+    ///
+    /// This accepts exactly the same input as `DirectoryItem::from_str`, and
+    /// reports the same errors.
+    // FromStr can't return a value borrowing from the input
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &'a str) -> Result<DirectoryItemRef<'a>, GopherError> {
+        let first = match s.chars().next() {
+            Some(c) if c.is_control() => {
+                return Err(GopherError::InvalidType { position: Position::new(1, 1), found: c });
+            },
+            Some(c) => c,
+            None => {
+                return Err(GopherError::MissingField { position: Position::new(1, 1), field: "type" });
+            },
+        };
+
+        let mut fields = s[first.len_utf8()..].splitn(5, '\t');
+        let mut next_field = |field: &'static str| fields.next().ok_or_else(|| {
+            GopherError::MissingField { position: Position::new(1, s.chars().count() + 1), field }
+        });
+
+        let name = next_field("name")?;
+        let selector = next_field("selector")?;
+        let host = next_field("host")?;
+        let port_text = next_field("port")?;
+        let extra = fields.next();
+
+        let port = match port_text.trim() {
+            "" => 70,
+            text => text.parse().map_err(|_| {
+                let start = first.len_utf8() + name.len() + selector.len() + host.len() + 3;
+                GopherError::BadPort {
+                    position: Position::new(1, s[..start].chars().count() + 1),
+                    port: text.into(),
+                }
+            })?,
+        };
+
+        Ok(DirectoryItemRef {
+            t: Type::from_char(first),
+            name,
+            selector,
+            host,
+            port,
+            extra,
+        })
+    }
+
+    /// Returns any fields following the port
+    pub fn extra_fields(&self) -> impl Iterator<Item = &'a str> {
+        self.extra.into_iter().flat_map(|extra| extra.split('\t'))
+    }
+
+    /// Copy the fields into an owned DirectoryItem
+    pub fn into_owned(self) -> DirectoryItem {
+        DirectoryItem {
+            t: self.t,
+            name: self.name.into(),
+            selector: self.selector.into(),
+            host: self.host.into(),
+            port: self.port,
+            extra: self.extra_fields().map(String::from).collect(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for DirectoryItemRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}\t{}\t{}\t{}", self.t.as_char(), self.name, self.selector, self.host, self.port)?;
+        if let Some(extra) = self.extra {
+            write!(f, "\t{}", extra)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> From<DirectoryItemRef<'a>> for DirectoryItem {
+    fn from(item: DirectoryItemRef<'a>) -> DirectoryItem {
+        item.into_owned()
+    }
+}
+
+/// A Gopher Directory of DirectoryItemRefs
+///
+/// ```
+/// use gopher::*;
+///
+/// let input = "0About internet Gopher\tStuff:About us\trawBits.micro.umn.edu\t70\r
+/// 1Courses, Schedules, Calendars\t\tevents.ais.umn.edu\t9120\r
+/// .\r
+/// ";
+///
+/// let directory = DirectoryRef::from_str(input).expect("failed to parse directory");
+/// assert_eq!(directory.items()[0].selector, "Stuff:About us");
+///
+/// let owned = directory.into_owned();
+/// assert_eq!(owned.items()[1].port, 9120);
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirectoryRef<'a> {
+    items: Vec<DirectoryItemRef<'a>>,
+}
+
+impl<'a> DirectoryRef<'a> {
+    /// Parse a &str into a DirectoryRef, stopping at the `.` terminator
+    // FromStr can't return a value borrowing from the input
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &'a str) -> Result<DirectoryRef<'a>, GopherError> {
+        let mut items = Vec::new();
+        for (n, line) in s.lines().enumerate() {
+            if line.trim() == "." {
+                break;
+            }
+            items.push(DirectoryItemRef::from_str(line).map_err(|e| e.at_line(n + 1))?);
+        }
+        Ok(DirectoryRef { items })
+    }
+
+    /// Returns the list of all DirectoryItemRefs, including info items
+    pub fn items(&self) -> &[DirectoryItemRef<'a>] {
+        &self.items
+    }
+
+    /// Copy every item into an owned Directory
+    pub fn into_owned(self) -> Directory {
+        Directory {
+            items: self.items.into_iter().map(DirectoryItemRef::into_owned).collect(),
+        }
+    }
+}
+
+impl<'a> fmt::Display for DirectoryRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for item in &self.items {
+            writeln!(f, "{}", item)?;
+        }
+        write!(f, ".")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_borrowed() {
+        let input = "iWelcome\tfake\t(NULL)\t0
+0About internet Gopher\tStuff:About us\trawBits.micro.umn.edu\t70
+1Gopher+\t/plus\tgopher.example.net\t\t+\t?
+.";
+        let directory = DirectoryRef::from_str(input).expect("failed to parse directory");
+        let items = directory.items();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[1].name, "About internet Gopher");
+        assert_eq!(items[2].port, 70);
+        assert_eq!(items[2].extra_fields().collect::<Vec<_>>(), vec!["+", "?"]);
+        assert_eq!(directory.to_string(), input.replace("\t\t+", "\t70\t+"));
+
+        let owned = directory.into_owned();
+        let parsed = Directory::from_str(input).unwrap();
+        assert_eq!(owned.to_string(), parsed.to_string());
+        assert_eq!(owned.items()[2].extra, parsed.items()[2].extra);
+    }
+
+    #[test]
+    fn parse_borrowed_errors() {
+        let lines = [
+            "",
+            "\u{7}bell",
+            "0Caf\u{e9}\t/cafe",
+            "0Caf\u{e9}\t/cafe\tgopher.example.net\tx",
+        ];
+        for line in &lines {
+            let borrowed = DirectoryItemRef::from_str(line).unwrap_err();
+            let owned = DirectoryItem::from_str(line).unwrap_err();
+            assert_eq!(borrowed.to_string(), owned.to_string());
+        }
+
+        let err = DirectoryRef::from_str("iWelcome\tfake\t(NULL)\t0\nnot an item\n").unwrap_err();
+        assert_eq!(err.position(), Some(Position::new(2, 12)));
+    }
+}
@@ -0,0 +1,269 @@
+//! Building Directories
+//!
+//! A `DirectoryBuilder` makes it easy to put together a menu in code, and
+//! checks that each item can be written out safely before building the
+//! `Directory`.
+//!
+//! ```
+//! use gopher::*;
+//!
+//! let directory = DirectoryBuilder::new("gopher.example.net", 70)
+//!     .info("Welcome to my gopherhole!")
+//!     .link(Type::File, "About", "/about.txt")
+//!     .search("Search", "/search")
+//!     .external_url("My website", "https://example.net/")
+//!     .try_build()
+//!     .expect("invalid directory");
+//!
+//! assert_eq!(directory.items().len(), 4);
+//! assert_eq!(directory.items()[1].to_string(), "0About\t/about.txt\tgopher.example.net\t70");
+//! ```
+
+use std::fmt;
+
+use Type;
+use DirectoryItem;
+use Directory;
+
+/// The longest selector allowed by RFC 1436, in bytes
+pub const MAX_SELECTOR_LENGTH: usize = 255;
+
+/// The kinds of problem which make an item unsafe to write out
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ViolationKind {
+    /// The field contains a tab, which would split it in two
+    Tab,
+    /// The field contains a carriage return or line feed, which would split
+    /// the item across lines
+    LineBreak,
+    /// A link item has port 0
+    PortZero,
+    /// The selector is longer than `MAX_SELECTOR_LENGTH`, with its length
+    SelectorTooLong(usize),
+    /// The item type is a control character, such as a tab or line break,
+    /// which can't start an item line
+    ControlType(char),
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ViolationKind::Tab => write!(f, "contains a tab"),
+            ViolationKind::LineBreak => write!(f, "contains a line break"),
+            ViolationKind::PortZero => write!(f, "is 0"),
+            ViolationKind::SelectorTooLong(len) =>
+                write!(f, "is {} bytes long, more than {}", len, MAX_SELECTOR_LENGTH),
+            ViolationKind::ControlType(c) => write!(f, "is the control character {:?}", c),
+        }
+    }
+}
+
+/// A problem with a field of an item in a directory
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Violation {
+    /// The index of the item in the directory
+    pub item: usize,
+    /// The name of the field, eg `selector`
+    pub field: &'static str,
+    pub kind: ViolationKind,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "item {}: {} {}", self.item, self.field, self.kind)
+    }
+}
+
+/// Check the fields of an item, adding any problems to `violations`
+fn validate_item(index: usize, item: &DirectoryItem, violations: &mut Vec<Violation>) {
+    let t = item.t.as_char();
+    if t.is_control() {
+        violations.push(Violation { item: index, field: "type", kind: ViolationKind::ControlType(t) });
+    }
+
+    let mut check = |field: &'static str, value: &str| {
+        if value.contains('\t') {
+            violations.push(Violation { item: index, field, kind: ViolationKind::Tab });
+        }
+        if value.contains(['\r', '\n']) {
+            violations.push(Violation { item: index, field, kind: ViolationKind::LineBreak });
+        }
+    };
+
+    check("name", &item.name);
+    check("selector", &item.selector);
+    check("host", &item.host);
+    for field in &item.extra {
+        // further fields can't contain tabs either, but may be empty
+        check("extra", field);
+    }
+
+    if item.selector.len() > MAX_SELECTOR_LENGTH {
+        violations.push(Violation {
+            item: index,
+            field: "selector",
+            kind: ViolationKind::SelectorTooLong(item.selector.len()),
+        });
+    }
+
+    if item.port == 0 && !item.is_info() {
+        violations.push(Violation { item: index, field: "port", kind: ViolationKind::PortZero });
+    }
+}
+
+impl Directory {
+    /// Check that every item can be written out without corrupting the
+    /// directory
+    pub fn validate(&self) -> Result<(), Vec<Violation>> {
+        let mut violations = Vec::new();
+        for (index, item) in self.items.iter().enumerate() {
+            validate_item(index, item, &mut violations);
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+/// A builder for Directories
+///
+/// Links are created on the builder's host and port unless given explicitly.
+#[derive(Clone, Debug)]
+pub struct DirectoryBuilder {
+    host: String,
+    port: u16,
+    items: Vec<DirectoryItem>,
+}
+
+impl DirectoryBuilder {
+    /// Create a builder for a directory on the given server
+    pub fn new(host: &str, port: u16) -> DirectoryBuilder {
+        DirectoryBuilder {
+            host: host.into(),
+            port,
+            items: Vec::new(),
+        }
+    }
+
+    /// Add some text, as one info line per line of text
+    ///
+    /// Empty lines are kept, and empty text adds a single blank info line,
+    /// for spacing out a menu.
+    pub fn info(mut self, text: &str) -> DirectoryBuilder {
+        let blank = if text.is_empty() { Some("") } else { None };
+        for line in text.lines().chain(blank) {
+            self.items.push(DirectoryItem {
+                t: Type::Info,
+                name: line.into(),
+                selector: String::new(),
+                host: String::new(),
+                port: 0,
+                extra: Vec::new(),
+            });
+        }
+        self
+    }
+
+    /// Add a link to an item on this server
+    pub fn link(self, t: Type, name: &str, selector: &str) -> DirectoryBuilder {
+        let (host, port) = (self.host.clone(), self.port);
+        self.remote_link(t, name, selector, &host, port)
+    }
+
+    /// Add a link to an item on another server
+    pub fn remote_link(mut self, t: Type, name: &str, selector: &str, host: &str, port: u16) -> DirectoryBuilder {
+        self.items.push(DirectoryItem {
+            t,
+            name: name.into(),
+            selector: selector.into(),
+            host: host.into(),
+            port,
+            extra: Vec::new(),
+        });
+        self
+    }
+
+    /// Add a search server on this server
+    pub fn search(self, name: &str, selector: &str) -> DirectoryBuilder {
+        self.link(Type::SearchServer, name, selector)
+    }
+
+    /// Add a link outside of gopherspace, with a `URL:` selector
+    pub fn external_url(mut self, name: &str, url: &str) -> DirectoryBuilder {
+        let item = DirectoryItem::from_external_url(name, url, &self.host, self.port);
+        self.items.push(item);
+        self
+    }
+
+    /// Add an existing item
+    pub fn item(mut self, item: DirectoryItem) -> DirectoryBuilder {
+        self.items.push(item);
+        self
+    }
+
+    /// Build the Directory, or report every problem found with its items
+    pub fn try_build(self) -> Result<Directory, Vec<Violation>> {
+        let directory = Directory { items: self.items };
+        directory.validate()?;
+        Ok(directory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_directory() {
+        let directory = DirectoryBuilder::new("gopher.example.net", 7070)
+            .info("First line\nSecond line")
+            .link(Type::Directory, "Files", "/files")
+            .remote_link(Type::File, "Elsewhere", "/elsewhere.txt", "gopher.example.org", 70)
+            .try_build()
+            .expect("failed to build directory");
+
+        assert_eq!(directory.to_string(), "iFirst line\t\t\t0
+iSecond line\t\t\t0
+1Files\t/files\tgopher.example.net\t7070
+0Elsewhere\t/elsewhere.txt\tgopher.example.org\t70
+.");
+    }
+
+    #[test]
+    fn build_blank_info() {
+        let directory = DirectoryBuilder::new("gopher.example.net", 70)
+            .info("Title")
+            .info("")
+            .info("One\n\nTwo")
+            .try_build()
+            .expect("failed to build directory");
+
+        let names: Vec<&str> = directory.items().iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["Title", "", "One", "", "Two"]);
+    }
+
+    #[test]
+    fn build_invalid_directory() {
+        let violations = DirectoryBuilder::new("gopher.example.net", 0)
+            .info("Fine")
+            .link(Type::File, "Tab\tin name", "/file.txt")
+            .remote_link(Type::File, "Line break", "/file\r\n.txt", "gopher.example.net", 70)
+            .remote_link(Type::File, "Long", &"x".repeat(300), "gopher.example.net", 70)
+            .remote_link(Type::Unknown('\n'), "Control", "/x", "gopher.example.net", 70)
+            .try_build()
+            .unwrap_err();
+
+        assert_eq!(violations, vec![
+            Violation { item: 1, field: "name", kind: ViolationKind::Tab },
+            Violation { item: 1, field: "port", kind: ViolationKind::PortZero },
+            Violation { item: 2, field: "selector", kind: ViolationKind::LineBreak },
+            Violation { item: 3, field: "selector", kind: ViolationKind::SelectorTooLong(300) },
+            Violation { item: 4, field: "type", kind: ViolationKind::ControlType('\n') },
+        ]);
+        assert_eq!(violations[3].to_string(), "item 3: selector is 300 bytes long, more than 255");
+        assert_eq!(violations[4].to_string(), "item 4: type is the control character '\\n'");
+    }
+}
@@ -0,0 +1,237 @@
+//! Directory Diffing
+//!
+//! Compares two versions of a menu, for watching phlogs and bulletin boards
+//! for new entries.  Links are matched by their type, selector, host and port,
+//! so a renamed link shows up as a change rather than a removal and an
+//! addition, and info lines are matched by their text.  Hosts are normalized
+//! as when comparing `DirectoryItem`s, once per item.
+
+use std::collections::{HashMap, VecDeque};
+
+use std::borrow::Cow;
+
+use Type;
+use DirectoryItem;
+use Directory;
+use normalize::normalized_host;
+
+/// A difference between two versions of a Directory
+///
+/// Indexes are into the old directory's items for `from`, and the new
+/// directory's items for `to`.
+#[derive(Clone, Debug)]
+pub enum Change<'a> {
+    /// An item only in the new directory
+    Added { to: usize, item: &'a DirectoryItem },
+    /// An item only in the old directory
+    Removed { from: usize, item: &'a DirectoryItem },
+    /// An unchanged item which has moved relative to the items around it
+    Moved { from: usize, to: usize, item: &'a DirectoryItem },
+    /// An item which has been renamed or otherwise changed, but links to the
+    /// same selector
+    Changed { from: usize, to: usize, old: &'a DirectoryItem, new: &'a DirectoryItem },
+}
+
+/// The differences between two versions of a Directory
+#[derive(Clone, Debug)]
+pub struct DirectoryDiff<'a> {
+    /// The removed items in their old order, then everything else in the new
+    /// order
+    pub changes: Vec<Change<'a>>,
+}
+
+impl<'a> DirectoryDiff<'a> {
+    /// Check whether the directories were the same
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The items only in the new directory
+    pub fn added(&self) -> Vec<&'a DirectoryItem> {
+        self.changes.iter().filter_map(|change| match *change {
+            Change::Added { item, .. } => Some(item),
+            _ => None,
+        }).collect()
+    }
+
+    /// The items only in the old directory
+    pub fn removed(&self) -> Vec<&'a DirectoryItem> {
+        self.changes.iter().filter_map(|change| match *change {
+            Change::Removed { item, .. } => Some(item),
+            _ => None,
+        }).collect()
+    }
+}
+
+/// The identity of an item when matching: links by where they point, and
+/// info lines by their text
+type Key<'a> = (Type, &'a str, Cow<'a, str>, u16);
+
+/// Find the key of an item, normalizing its host and port
+fn key(item: &DirectoryItem) -> Key<'_> {
+    if item.is_info() {
+        (Type::Info, &item.name, Cow::Borrowed(""), 0)
+    } else {
+        let port = if item.port == 0 { 70 } else { item.port };
+        (item.t, &item.selector, normalized_host(&item.host), port)
+    }
+}
+
+impl Directory {
+    /// Compare this directory with a newer version of it
+    ///
+    /// ```
+    /// use gopher::*;
+    ///
+    /// let old = Directory::from_str("0First post\t/first.txt\tgopher.example.net\t70
+    /// .").unwrap();
+    /// let new = Directory::from_str("0Second post\t/second.txt\tgopher.example.net\t70
+    /// 0First post (updated)\t/first.txt\tgopher.example.net\t70
+    /// .").unwrap();
+    ///
+    /// let diff = old.diff(&new);
+    /// assert_eq!(diff.added()[0].name, "Second post");
+    /// match diff.changes[1] {
+    ///     Change::Changed { from: 0, to: 1, new, .. } => assert_eq!(new.name, "First post (updated)"),
+    ///     ref other => panic!("unexpected change {:?}", other),
+    /// }
+    /// ```
+    pub fn diff<'a>(&'a self, new: &'a Directory) -> DirectoryDiff<'a> {
+        let old_items = &self.items;
+        let new_items = &new.items;
+        let old_keys: Vec<Key> = old_items.iter().map(key).collect();
+        let new_keys: Vec<Key> = new_items.iter().map(key).collect();
+
+        // match identical keys, in order, so repeated info lines pair up
+        let mut unmatched: HashMap<&Key, VecDeque<usize>> = HashMap::new();
+        for (i, key) in old_keys.iter().enumerate() {
+            unmatched.entry(key).or_default().push_back(i);
+        }
+        let mut old_for_new: Vec<Option<usize>> = new_keys.iter()
+            .map(|key| unmatched.get_mut(key).and_then(VecDeque::pop_front))
+            .collect();
+
+        // then match links whose type has changed by their selector
+        let mut by_selector: HashMap<_, VecDeque<usize>> = HashMap::new();
+        for &i in unmatched.values().flatten() {
+            let (t, selector, ref host, port) = old_keys[i];
+            if t != Type::Info && !selector.is_empty() {
+                by_selector.entry((selector, host, port)).or_default().push_back(i);
+            }
+        }
+        for queue in by_selector.values_mut() {
+            queue.make_contiguous().sort_unstable();
+        }
+        for (j, &(t, selector, ref host, port)) in new_keys.iter().enumerate() {
+            if old_for_new[j].is_none() && t != Type::Info {
+                old_for_new[j] = by_selector.get_mut(&(selector, host, port))
+                    .and_then(VecDeque::pop_front);
+            }
+        }
+
+        let mut matched_old = vec![false; old_items.len()];
+        for &i in old_for_new.iter().flatten() {
+            matched_old[i] = true;
+        }
+        let in_order = longest_increasing(&old_for_new);
+
+        let mut changes: Vec<Change> = old_items.iter().enumerate()
+            .filter(|&(i, _)| !matched_old[i])
+            .map(|(from, item)| Change::Removed { from, item })
+            .collect();
+
+        for (to, item) in new_items.iter().enumerate() {
+            match old_for_new[to] {
+                None => changes.push(Change::Added { to, item }),
+                Some(from) => {
+                    let old = &old_items[from];
+                    if old.t != item.t || old.name != item.name || old.extra != item.extra {
+                        changes.push(Change::Changed { from, to, old, new: item });
+                    } else if !in_order[to] {
+                        changes.push(Change::Moved { from, to, item });
+                    }
+                }
+            }
+        }
+
+        DirectoryDiff { changes }
+    }
+}
+
+/// Find the matched items which haven't moved, as the longest run of items
+/// whose old indexes are still increasing
+fn longest_increasing(indexes: &[Option<usize>]) -> Vec<bool> {
+    // tails[k] is the position of the smallest tail of an increasing run of
+    // length k + 1, and previous links each position to the one before it
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; indexes.len()];
+    for (j, index) in indexes.iter().enumerate() {
+        let index = match *index {
+            Some(index) => index,
+            None => continue,
+        };
+        let k = tails.partition_point(|&t| indexes[t] < Some(index));
+        if k > 0 {
+            previous[j] = Some(tails[k - 1]);
+        }
+        if k == tails.len() {
+            tails.push(j);
+        } else {
+            tails[k] = j;
+        }
+    }
+
+    let mut in_order = vec![false; indexes.len()];
+    let mut next = tails.last().cloned();
+    while let Some(j) = next {
+        in_order[j] = true;
+        next = previous[j];
+    }
+    in_order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_directories() {
+        let old = Directory::from_str("iPhlog\t\t\t0
+i\t\t\t0
+0Entry one\t/one.txt\tgopher.example.net\t70
+0Entry two\t/two.txt\tgopher.example.net\t70
+0Entry three\t/three.txt\tgopher.example.net\t70
+1Archive\t/archive\tgopher.example.net\t70
+0Gone\t/gone.txt\tgopher.example.net\t70
+.").unwrap();
+        let new = Directory::from_str("iPhlog\t\t\t0
+i\t\t\t0
+0Entry four\t/four.txt\tgopher.example.net\t70
+0Entry three\t/three.txt\tgopher.example.net\t70
+0Entry one\t/one.txt\tgopher.example.net\t70
+0Entry two, revised\t/two.txt\tgopher.example.net\t70
+9Archive\t/archive\tgopher.example.net\t70
+.").unwrap();
+
+        let diff = old.diff(&new);
+        let changes: Vec<_> = diff.changes.iter().map(|change| match *change {
+            Change::Added { to, item } => ('+', None, Some(to), &item.name),
+            Change::Removed { from, item } => ('-', Some(from), None, &item.name),
+            Change::Moved { from, to, item } => ('>', Some(from), Some(to), &item.name),
+            Change::Changed { from, to, new, .. } => ('~', Some(from), Some(to), &new.name),
+        }).collect();
+        assert_eq!(changes, vec![
+            ('-', Some(6), None, &String::from("Gone")),
+            ('+', None, Some(2), &String::from("Entry four")),
+            ('>', Some(4), Some(3), &String::from("Entry three")),
+            ('~', Some(3), Some(5), &String::from("Entry two, revised")),
+            ('~', Some(5), Some(6), &String::from("Archive")),
+        ]);
+        assert_eq!(diff.removed()[0].name, "Gone");
+
+        assert!(old.diff(&old).is_empty());
+
+        let respelled = Directory::from_str(&old.to_string().replace("gopher.example.net", "Gopher.Example.NET.")).unwrap();
+        assert!(old.diff(&respelled).is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+//! Error Handling
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::str::Utf8Error;
+
+/// A position in a response, for reporting parse errors
+///
+/// Lines and columns both start from 1, and columns count characters rather
+/// than bytes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Errors from parsing or fetching Gopher resources
+#[derive(Debug)]
+pub enum GopherError {
+    /// Any other IO error
+    Io(io::Error),
+    /// The server took too long to respond
+    Timeout(io::Error),
+    /// The server refused the connection
+    ConnectionRefused(io::Error),
+    /// The response was larger than the configured limit, in bytes
+    ResponseTooLarge { limit: u64 },
+    /// The transfer was cancelled with a `CancellationToken`
+    Cancelled,
+    /// The response was not valid UTF-8
+    NonUtf8 { position: Position, source: Utf8Error },
+    /// A directory item had a port which was not a number from 0 to 65535
+    BadPort { position: Position, port: String },
+    /// A directory item was missing one of its tab-separated fields
+    MissingField { position: Position, field: &'static str },
+    /// A directory item started with a character which can't be an item type
+    InvalidType { position: Position, found: char },
+    /// A `gopher://` URL could not be parsed
+    ParseUrl(String),
+    /// A set of Gopher+ attribute blocks could not be parsed
+    ParseAttributes(String),
+    /// An answer was not valid for a Gopher+ ASK form question
+    InvalidAnswer(String),
+    /// A search query contained a tab or line break, which would end it early
+    InvalidQuery(String),
+    /// A regular expression for matching items could not be parsed
+    InvalidPattern(String),
+    /// The server reported an error
+    Server(String),
+    /// The selector was a `URL:` link outside of gopherspace, which shouldn't
+    /// be sent to a server
+    ExternalUrl(String),
+}
+
+impl GopherError {
+    /// The position of the error in the response, if it has one
+    pub fn position(&self) -> Option<Position> {
+        match *self {
+            GopherError::NonUtf8 { position, .. } |
+            GopherError::BadPort { position, .. } |
+            GopherError::MissingField { position, .. } |
+            GopherError::InvalidType { position, .. } => Some(position),
+            _ => None,
+        }
+    }
+
+    /// Move the position of the error to the given line, for errors found
+    /// while parsing a single line of a larger response
+    pub fn at_line(mut self, line: usize) -> GopherError {
+        match self {
+            GopherError::NonUtf8 { ref mut position, .. } |
+            GopherError::BadPort { ref mut position, .. } |
+            GopherError::MissingField { ref mut position, .. } |
+            GopherError::InvalidType { ref mut position, .. } => position.line = line,
+            _ => {}
+        }
+        self
+    }
+
+    /// Build a `NonUtf8` error for invalid data, finding the line and column
+    /// of the first invalid byte
+    pub(crate) fn non_utf8(data: &[u8], source: Utf8Error) -> GopherError {
+        // the prefix is known to be valid
+        let valid = String::from_utf8_lossy(&data[..source.valid_up_to()]);
+        let line = valid.matches('\n').count() + 1;
+        let column = valid.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        GopherError::NonUtf8 { position: Position::new(line, column), source }
+    }
+}
+
+impl fmt::Display for GopherError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GopherError::Io(ref e) => write!(f, "{}", e),
+            GopherError::Timeout(ref e) => write!(f, "timed out: {}", e),
+            GopherError::ConnectionRefused(ref e) => write!(f, "connection refused: {}", e),
+            GopherError::ResponseTooLarge { limit } =>
+                write!(f, "response larger than {} bytes", limit),
+            GopherError::Cancelled => write!(f, "transfer cancelled"),
+            GopherError::NonUtf8 { position, .. } =>
+                write!(f, "invalid UTF-8 at {}", position),
+            GopherError::BadPort { position, ref port } =>
+                write!(f, "bad port {:?} at {}", port, position),
+            GopherError::MissingField { position, field } =>
+                write!(f, "missing {} field at {}", field, position),
+            GopherError::InvalidType { position, found } =>
+                write!(f, "invalid item type {:?} at {}", found, position),
+            GopherError::ParseUrl(ref url) => write!(f, "invalid gopher url {:?}", url),
+            GopherError::ParseAttributes(ref s) => write!(f, "invalid Gopher+ attributes: {}", s),
+            GopherError::InvalidAnswer(ref s) => write!(f, "invalid answer to {}", s),
+            GopherError::InvalidQuery(ref query) => write!(f, "invalid search query {:?}", query),
+            GopherError::InvalidPattern(ref message) => write!(f, "invalid pattern: {}", message),
+            GopherError::Server(ref message) => write!(f, "server error: {}", message),
+            GopherError::ExternalUrl(ref url) => write!(f, "external link to {}", url),
+        }
+    }
+}
+
+impl error::Error for GopherError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            GopherError::Io(ref e) |
+            GopherError::Timeout(ref e) |
+            GopherError::ConnectionRefused(ref e) => Some(e),
+            GopherError::NonUtf8 { ref source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GopherError {
+    fn from(io: io::Error) -> GopherError {
+        // errors from a limit on a `ResponseReader` are passed through `Read`
+        // wrapped in an io::Error
+        if io.get_ref().is_some_and(|inner| inner.is::<GopherError>()) {
+            if let Ok(inner) = io.into_inner().unwrap().downcast::<GopherError>() {
+                return *inner;
+            }
+            unreachable!("checked the inner error type");
+        }
+        match io.kind() {
+            // read timeouts are reported as WouldBlock on some platforms
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => GopherError::Timeout(io),
+            io::ErrorKind::ConnectionRefused => GopherError::ConnectionRefused(io),
+            _ => GopherError::Io(io),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn error_source() {
+        let err = GopherError::from(io::Error::new(io::ErrorKind::TimedOut, "too slow"));
+        match err {
+            GopherError::Timeout(_) => {},
+            ref other => panic!("unexpected error {:?}", other),
+        }
+        assert_eq!(err.to_string(), "timed out: too slow");
+        assert_eq!(err.source().map(|e| e.to_string()), Some(String::from("too slow")));
+
+        let data = [&b"first line\nsecond "[..], &[0xe9], &b" line"[..]].concat();
+        let utf8 = ::std::str::from_utf8(&data).unwrap_err();
+        let err = GopherError::non_utf8(&data, utf8);
+        assert_eq!(err.position(), Some(Position::new(2, 8)));
+        assert_eq!(err.to_string(), "invalid UTF-8 at line 2, column 8");
+        assert!(err.source().is_some());
+    }
+}
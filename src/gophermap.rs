@@ -0,0 +1,358 @@
+//! Gophermap Files
+//!
+//! Servers such as Bucktooth and Gophernicus build their menus from gophermap
+//! files, which are much less strict than the menus sent over the wire:
+//!
+//! - lines without a tab are info text
+//! - `Xname\tselector\thost\tport` is a link, and the host and port may be
+//!   left off to link to the same server
+//! - selectors not starting with `/` are relative to the gophermap's own
+//!   selector, and an empty selector means the name is the selector
+//! - `#` starts a comment, `=path` includes another file or script, `*` lists
+//!   the rest of the directory, and `.` ends the map
+//!
+//! ```
+//! use gopher::*;
+//! use gopher::gophermap::{self, Base};
+//!
+//! let input = "Welcome to my phlog!
+//! ## this is a comment
+//! 0First post\tfirst-post.txt
+//! 1Home\t/\tgopher.example.net\t70
+//! ";
+//!
+//! let base = Base::new("gopher.example.net", 7070, "/phlog");
+//! let directory = gophermap::parse(input, &base).expect("failed to parse gophermap");
+//!
+//! assert_eq!(directory.items().len(), 3);
+//! assert_eq!(directory.items()[1].selector, "/phlog/first-post.txt");
+//! assert_eq!(directory.items()[1].port, 7070);
+//! ```
+
+use GopherError;
+use Position;
+use Type;
+use DirectoryItem;
+use Directory;
+use external_url;
+
+/// The server and selector a gophermap is served from, used to fill in
+/// omitted hosts and ports and to resolve relative selectors
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Base {
+    pub host: String,
+    pub port: u16,
+    /// The selector of the menu itself, such as `/phlog`
+    pub selector: String,
+}
+
+impl Base {
+    pub fn new(host: &str, port: u16, selector: &str) -> Base {
+        Base {
+            host: host.into(),
+            port,
+            selector: selector.into(),
+        }
+    }
+
+    /// The selector which relative selectors are appended to, without a
+    /// trailing `/`
+    fn directory(&self) -> &str {
+        self.selector.trim_end_matches('/')
+    }
+
+    /// Resolve a selector relative to this gophermap
+    fn resolve(&self, selector: &str) -> String {
+        if selector.starts_with('/') || external_url(selector).is_some() {
+            selector.into()
+        } else {
+            format!("{}/{}", self.directory(), selector)
+        }
+    }
+
+    /// Make a selector relative to this gophermap, if it's below it
+    fn relative<'a>(&self, selector: &'a str) -> Option<&'a str> {
+        selector.strip_prefix(self.directory())
+            .and_then(|rest| rest.strip_prefix('/'))
+            .filter(|rest| !rest.is_empty() && !rest.starts_with('/') && external_url(rest).is_none())
+    }
+}
+
+/// A line of a gophermap
+#[derive(Clone, Debug)]
+pub enum Line {
+    /// A link or info line
+    Item(DirectoryItem),
+    /// A `=` line, including the named file, or the output of the named
+    /// script
+    Include(String),
+    /// A `*` line, listing the rest of the files in the directory
+    AutoList,
+    /// A `#` line, without the `#`
+    Comment(String),
+}
+
+/// A parsed gophermap, keeping the include, auto-list and comment lines
+/// which can't be turned into items on their own
+#[derive(Clone, Debug)]
+pub struct Gophermap {
+    pub lines: Vec<Line>,
+}
+
+impl Gophermap {
+    /// Parse a gophermap, stopping at a `.` line if there is one
+    pub fn from_str(s: &str, base: &Base) -> Result<Gophermap, GopherError> {
+        let mut lines = Vec::new();
+        for (n, line) in s.lines().enumerate() {
+            let line = if let Some(comment) = line.strip_prefix('#') {
+                Line::Comment(comment.into())
+            } else if let Some(path) = line.strip_prefix('=') {
+                Line::Include(path.trim().into())
+            } else if line.trim_end() == "*" {
+                Line::AutoList
+            } else if line.trim_end() == "." {
+                break;
+            } else {
+                Line::Item(parse_item(line, base).map_err(|e| e.at_line(n + 1))?)
+            };
+            lines.push(line);
+        }
+        Ok(Gophermap { lines })
+    }
+
+    /// Convert into a Directory, leaving out the includes and auto-lists
+    pub fn to_directory(&self) -> Directory {
+        self.resolve(|_| Vec::new())
+    }
+
+    /// Convert into a Directory, replacing each include and auto-list line
+    /// with the items returned by `expand`
+    pub fn resolve<F: FnMut(&Line) -> Vec<DirectoryItem>>(&self, mut expand: F) -> Directory {
+        let mut items = Vec::new();
+        for line in &self.lines {
+            match *line {
+                Line::Item(ref item) => items.push(item.clone()),
+                Line::Include(_) | Line::AutoList => items.extend(expand(line)),
+                Line::Comment(_) => {},
+            }
+        }
+        Directory { items }
+    }
+}
+
+/// Parse a gophermap into a Directory, leaving out any includes and
+/// auto-lists
+pub fn parse(s: &str, base: &Base) -> Result<Directory, GopherError> {
+    Gophermap::from_str(s, base).map(|map| map.to_directory())
+}
+
+/// Parse a single item line, reporting errors as if it was on line 1
+fn parse_item(line: &str, base: &Base) -> Result<DirectoryItem, GopherError> {
+    if !line.contains('\t') {
+        return Ok(info(line));
+    }
+
+    let mut fields = line.split('\t');
+    let first = fields.next().unwrap_or("");
+    let mut chars = first.chars();
+    let t = match chars.next() {
+        Some(c) => Type::from_char(c),
+        None => return Ok(info(line.trim())),
+    };
+    let name = chars.as_str();
+    let selector = fields.next().unwrap_or("");
+    let host_field = fields.next().unwrap_or("");
+    let host = Some(host_field.trim()).filter(|host| !host.is_empty());
+    let port = match fields.next().map(str::trim) {
+        None | Some("") if t == Type::Info => 0,
+        None | Some("") => base.port,
+        Some(port) => port.parse().map_err(|_| {
+            let start = first.len() + selector.len() + host_field.len() + 3;
+            GopherError::BadPort {
+                position: Position::new(1, line[..start].chars().count() + 1),
+                port: port.into(),
+            }
+        })?,
+    };
+
+    let (selector, host) = match host {
+        Some(host) => (selector.to_string(), host.to_string()),
+        None if t == Type::Info => (selector.to_string(), String::new()),
+        None if selector.is_empty() => (base.resolve(name), base.host.clone()),
+        None => (base.resolve(selector), base.host.clone()),
+    };
+
+    Ok(DirectoryItem {
+        t,
+        name: name.into(),
+        selector,
+        host,
+        port,
+        extra: fields.map(String::from).collect(),
+    })
+}
+
+fn info(text: &str) -> DirectoryItem {
+    DirectoryItem {
+        t: Type::Info,
+        name: text.into(),
+        selector: String::new(),
+        host: String::new(),
+        port: 0,
+        extra: Vec::new(),
+    }
+}
+
+/// Write a Directory as a compact gophermap
+///
+/// Info lines are written as bare text where possible, and hosts, ports and
+/// selectors are left out or made relative where they match `base`.
+///
+/// Items of type `#` or `=` can't be written, since their lines would be read
+/// back as a comment or an include, and give an `InvalidType` error at their
+/// line.
+pub fn write(directory: &Directory, base: &Base) -> Result<String, GopherError> {
+    let mut out = String::new();
+    for (n, item) in directory.items().iter().enumerate() {
+        let t = item.t.as_char();
+        if t == '#' || t == '=' {
+            return Err(GopherError::InvalidType { position: Position::new(n + 1, 1), found: t });
+        }
+
+        if item.t == Type::Info && item.selector.is_empty() && item.extra.is_empty() && is_bare_text(&item.name) {
+            out.push_str(&item.name);
+        } else if item.t == Type::Info {
+            out.push_str(&format!("i{}\t{}", item.name, item.selector));
+            if !item.extra.is_empty() {
+                out.push_str(&format!("\t{}\t{}", item.host, item.port));
+            }
+        } else {
+            // selectors without a host are resolved against the base when
+            // read back, so the host can only be left out if that gives the
+            // same selector
+            let relative = base.relative(&item.selector).unwrap_or(&item.selector);
+            let local = item.host == base.host && item.port == base.port && item.extra.is_empty() &&
+                !item.selector.is_empty() && base.resolve(relative) == item.selector;
+
+            if local {
+                out.push_str(&format!("{}{}\t{}", item.t.as_char(), item.name, relative));
+            } else {
+                out.push_str(&format!("{}{}\t{}\t{}\t{}", item.t.as_char(), item.name, item.selector, item.host, item.port));
+            }
+        }
+        for field in &item.extra {
+            out.push('\t');
+            out.push_str(field);
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Check whether info text can be written without a type or tab, without
+/// being mistaken for something else
+fn is_bare_text(text: &str) -> bool {
+    !text.contains('\t') &&
+        !text.starts_with(['#', '=']) &&
+        text.trim_end() != "*" &&
+        text.trim_end() != "."
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_gophermap() {
+        let input = "Welcome!
+# comment
+=/usr/local/bin/fortune
+
+iExplicit info\t
+0Relative\tpost.txt
+0Absolute\t/about.txt
+9archive.zip\t
+1Other port\tsub\t\t7071
+1Elsewhere\tdocs\tgopher.example.org\t70\t+
+hWeb\tURL:https://example.net/
+*
+.
+not read";
+        let base = Base::new("gopher.example.net", 70, "/phlog/");
+        let map = Gophermap::from_str(input, &base).expect("failed to parse gophermap");
+        assert_eq!(map.lines.len(), 12);
+
+        let directory = map.resolve(|line| match *line {
+            Line::AutoList => vec![info("listed")],
+            _ => Vec::new(),
+        });
+        assert_eq!(directory.to_string(), "iWelcome!\t\t\t0
+i\t\t\t0
+iExplicit info\t\t\t0
+0Relative\t/phlog/post.txt\tgopher.example.net\t70
+0Absolute\t/about.txt\tgopher.example.net\t70
+9archive.zip\t/phlog/archive.zip\tgopher.example.net\t70
+1Other port\t/phlog/sub\tgopher.example.net\t7071
+1Elsewhere\tdocs\tgopher.example.org\t70\t+
+hWeb\tURL:https://example.net/\tgopher.example.net\t70
+ilisted\t\t\t0
+.");
+
+        let err = Gophermap::from_str("Hello\n1Bad\t/\thost\tport\n", &base).unwrap_err();
+        assert_eq!(err.position(), Some(Position::new(2, 13)));
+    }
+
+    #[test]
+    fn write_gophermap() {
+        let base = Base::new("gopher.example.net", 70, "/phlog");
+        let input = "Welcome!
+# a comment
+0Relative\tpost.txt
+0Absolute\t/about.txt
+1Other port\tsub\tgopher.example.net\t7071
+1Root\t\tgopher.example.net\t70
+1Elsewhere\tdocs\tgopher.example.org\t70\t+
+";
+        let directory = parse(input, &base).unwrap();
+        let written = write(&directory, &base).unwrap();
+        assert_eq!(written, "Welcome!
+0Relative\tpost.txt
+0Absolute\t/about.txt
+1Other port\tsub\tgopher.example.net\t7071
+1Root\t\tgopher.example.net\t70
+1Elsewhere\tdocs\tgopher.example.org\t70\t+
+");
+        assert_eq!(parse(&written, &base).unwrap().to_string(), directory.to_string());
+
+        let directory = ::DirectoryBuilder::new("gopher.example.net", 70)
+            .link(Type::File, "About", "Stuff:About us")
+            .remote_link(Type::Directory, "Other port", "/phlog/sub", "gopher.example.net", 7071)
+            .try_build()
+            .unwrap();
+        let written = write(&directory, &base).unwrap();
+        assert_eq!(written, "0About\tStuff:About us\tgopher.example.net\t70
+1Other port\t/phlog/sub\tgopher.example.net\t7071
+");
+        assert_eq!(parse(&written, &base).unwrap().to_string(), directory.to_string());
+
+        let directory = ::DirectoryBuilder::new("gopher.example.net", 70)
+            .info("# looks like a comment")
+            .try_build()
+            .unwrap();
+        let written = write(&directory, &base).unwrap();
+        assert_eq!(written, "i# looks like a comment\t\n");
+        assert_eq!(parse(&written, &base).unwrap().to_string(), directory.to_string());
+
+        let directory = ::DirectoryBuilder::new("gopher.example.net", 70)
+            .link(Type::File, "Fine", "/fine.txt")
+            .link(Type::Unknown('#'), "Hash", "/hash")
+            .try_build()
+            .unwrap();
+        let err = write(&directory, &base).unwrap_err();
+        assert_eq!(err.position(), Some(Position::new(2, 1)));
+        match err {
+            GopherError::InvalidType { found: '#', .. } => {},
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+}
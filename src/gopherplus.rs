@@ -0,0 +1,825 @@
+//! Gopher+ Extensions
+//!
+//! This module implements the client side of the item attribute requests
+//! described in the [Gopher+ protocol](https://github.com/gopher-protocol/gopher-plus).
+//! Servers which mark their items with a `+` in the fifth field can be asked
+//! for an item's attributes by sending `selector\t!`, which returns a set of
+//! attribute blocks:
+//!
+//! ```text
+//! +INFO: 0About this server    /about    gopher.example.net    70    +
+//! +ADMIN:
+//!  Admin: Gopher Admin <admin@example.net>
+//!  Mod-Date: Wed Jul 28 17:02:01 1993 <19930728170201>
+//! +VIEWS:
+//!  text/plain: <1k>
+//! +ABSTRACT:
+//!  A short description of this server.
+//! ```
+
+use std::net::ToSocketAddrs;
+use std::ops::Range;
+
+use net::{self, Client};
+use GopherError;
+use DirectoryItem;
+use Charset;
+
+/// A representation of an item offered by a Gopher+ server, as listed in
+/// the `+VIEWS` block
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct View {
+    /// The MIME type of this view, eg `text/plain`
+    pub mime: String,
+    /// The language of this view, if specified, eg `En_US`
+    pub language: Option<String>,
+    /// The approximate size given by the server, eg `10k`
+    pub size: Option<String>,
+}
+
+impl View {
+    /// Parse a line from a `+VIEWS` block, such as `text/plain En_US: <10k>`
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<View, GopherError> {
+        let s = s.trim();
+        let (description, size) = match s.find(':') {
+            Some(idx) => (&s[..idx], s[idx+1..].trim()),
+            None => (s, ""),
+        };
+
+        let mut words = description.split_whitespace();
+        let mime = match words.next() {
+            Some(mime) => mime,
+            None => return Err(GopherError::ParseAttributes(s.into())),
+        };
+        let language = words.next();
+
+        let size = size.trim_start_matches('<').trim_end_matches('>');
+
+        Ok(View {
+            mime: mime.into(),
+            language: language.map(String::from),
+            size: if size.is_empty() { None } else { Some(size.into()) },
+        })
+    }
+
+    /// The representation to request from the server, eg `text/plain En_US`
+    pub fn representation(&self) -> String {
+        match self.language {
+            Some(ref language) => format!("{} {}", self.mime, language),
+            None => self.mime.clone(),
+        }
+    }
+
+    /// Check whether this view is text, rather than binary data such as an
+    /// image
+    ///
+    /// Text views are `text/*`, and the Gopher menu types.
+    pub fn is_text(&self) -> bool {
+        let mime = self.mime.to_ascii_lowercase();
+        mime.starts_with("text/") || mime == "application/gopher-menu" || mime == "application/gopher+-menu"
+    }
+
+    /// Check whether this view matches a preference such as `text/plain`,
+    /// `text/*` or `text/plain En_US`
+    ///
+    /// MIME types and languages are compared case-insensitively, and a
+    /// preference without a language matches views in any language.
+    pub fn matches(&self, preference: &str) -> bool {
+        let mut words = preference.split_whitespace();
+        let mime = words.next().unwrap_or("");
+        let mime_matches = match mime.strip_suffix("/*") {
+            Some(major) => self.mime.split('/').next()
+                .is_some_and(|m| m.eq_ignore_ascii_case(major)),
+            None => mime == "*" || self.mime.eq_ignore_ascii_case(mime),
+        };
+
+        let language_matches = match (words.next(), self.language.as_ref()) {
+            (None, _) => true,
+            (Some(wanted), Some(language)) => language.eq_ignore_ascii_case(wanted),
+            (Some(_), None) => false,
+        };
+
+        mime_matches && language_matches
+    }
+}
+
+impl std::str::FromStr for View {
+    type Err = GopherError;
+    fn from_str(s: &str) -> Result<View, GopherError> {
+        View::from_str(s)
+    }
+}
+
+/// The content of a view, which is only decoded if the view is text
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ViewData {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl ViewData {
+    /// Take the body of a response as the content of a view, decoding text
+    /// with a guessed character set
+    fn new(view: &View, body: &[u8]) -> ViewData {
+        if view.is_text() {
+            ViewData::Text(Charset::detect(body).decode(body))
+        } else {
+            ViewData::Binary(body.to_vec())
+        }
+    }
+}
+
+/// A single attribute block, such as `+ADMIN`, with its lines of content
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AttributeBlock {
+    /// The name of the block, without the leading `+` or trailing `:`
+    pub name: String,
+    /// Any text following the block name on the same line
+    pub value: String,
+    /// The content lines of the block, with the leading space removed
+    pub lines: Vec<String>,
+}
+
+/// The kinds of question that can appear in a `+ASK` block
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AskKind {
+    /// `Ask:` a single line of text
+    Ask,
+    /// `AskP:` a password, which should not be echoed
+    AskPassword,
+    /// `AskL:` several lines of text
+    AskLong,
+    /// `Choose:` one of a list of choices
+    Choose,
+    /// `Select:` a yes/no checkbox, answered with `1` or `0`
+    Select,
+    /// `Note:` text to show to the user, with no answer
+    Note,
+    /// Any other keyword, such as `AskF:` to upload a file, which is kept so
+    /// the answers to the other questions stay in order
+    ///
+    /// These are treated as questions answered with a single line of text.
+    Other(String),
+}
+
+impl AskKind {
+    /// Look up a question kind by its keyword, eg `AskP`
+    pub fn from_keyword(s: &str) -> Option<AskKind> {
+        match s {
+            "Ask" => Some(AskKind::Ask),
+            "AskP" => Some(AskKind::AskPassword),
+            "AskL" => Some(AskKind::AskLong),
+            "Choose" => Some(AskKind::Choose),
+            "Select" => Some(AskKind::Select),
+            "Note" => Some(AskKind::Note),
+            _ => None,
+        }
+    }
+
+    /// Convert back into a keyword
+    pub fn as_keyword(&self) -> &str {
+        match *self {
+            AskKind::Ask => "Ask",
+            AskKind::AskPassword => "AskP",
+            AskKind::AskLong => "AskL",
+            AskKind::Choose => "Choose",
+            AskKind::Select => "Select",
+            AskKind::Note => "Note",
+            AskKind::Other(ref keyword) => keyword,
+        }
+    }
+}
+
+/// A single question in an ASK form
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AskField {
+    pub kind: AskKind,
+    pub prompt: String,
+    /// The tab-separated values following the prompt: the default answer for
+    /// `Ask`, `AskP`, `AskL` and `Select`, or the list of choices for `Choose`
+    pub options: Vec<String>,
+    /// The current answer, initially the default
+    pub answer: String,
+}
+
+impl AskField {
+    /// Parse a line from a `+ASK` block, such as `Ask: Your name?\tdefault`
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<AskField, GopherError> {
+        let err = || GopherError::ParseAttributes(s.into());
+
+        let idx = s.find(':').ok_or_else(&err)?;
+        let keyword = s[..idx].trim();
+        let kind = match AskKind::from_keyword(keyword) {
+            Some(kind) => kind,
+            None if !keyword.is_empty() && keyword.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') =>
+                AskKind::Other(keyword.into()),
+            None => return Err(err()),
+        };
+
+        let mut fields = s[idx+1..].split('\t');
+        let prompt = fields.next().unwrap_or("").trim().to_string();
+        let options: Vec<String> = fields.map(String::from).collect();
+
+        let answer = match kind {
+            AskKind::Note => String::new(),
+            AskKind::Select => match options.first() {
+                Some(default) if default.trim() == "1" => "1".into(),
+                _ => "0".into(),
+            },
+            _ => options.first().cloned().unwrap_or_default(),
+        };
+
+        Ok(AskField { kind, prompt, options, answer })
+    }
+
+    /// Check whether this field expects an answer
+    pub fn is_question(&self) -> bool {
+        self.kind != AskKind::Note
+    }
+
+    /// Set the answer to this field, checking that it is valid for the kind
+    /// of question
+    pub fn set_answer(&mut self, answer: &str) -> Result<(), GopherError> {
+        let valid = match self.kind {
+            AskKind::Note => false,
+            AskKind::AskLong => !answer.contains('\t'),
+            AskKind::Choose => self.options.iter().any(|choice| choice == answer),
+            AskKind::Select => answer == "0" || answer == "1",
+            AskKind::Ask | AskKind::AskPassword | AskKind::Other(_) => !answer.contains(['\t', '\r', '\n']),
+        };
+
+        if valid {
+            self.answer = answer.into();
+            Ok(())
+        } else {
+            Err(GopherError::InvalidAnswer(format!("{}: {}", self.prompt, answer)))
+        }
+    }
+}
+
+impl std::str::FromStr for AskField {
+    type Err = GopherError;
+    fn from_str(s: &str) -> Result<AskField, GopherError> {
+        AskField::from_str(s)
+    }
+}
+
+/// A form described by a `+ASK` block
+///
+/// ```
+/// use gopher::gopherplus::*;
+///
+/// let mut form = AskForm::from_str("Ask: Your name?\tAnonymous
+/// Choose: Format\tplain\thtml
+/// Select: Subscribe?\t1").expect("failed to parse form");
+///
+/// form.fields[0].set_answer("Peter").unwrap();
+/// form.fields[1].set_answer("html").unwrap();
+/// assert_eq!(form.data_block(), "+-1\r\nPeter\r\nhtml\r\n1\r\n.\r\n");
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AskForm {
+    pub fields: Vec<AskField>,
+}
+
+impl AskForm {
+    /// Parse the lines of a `+ASK` block
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<AskForm, GopherError> {
+        let mut fields = Vec::new();
+        for line in s.lines() {
+            let line = line.strip_prefix(' ').unwrap_or(line);
+            if !line.trim().is_empty() {
+                fields.push(AskField::from_str(line)?);
+            }
+        }
+        Ok(AskForm { fields })
+    }
+
+    /// Iterate over the fields which expect an answer
+    pub fn questions_mut(&mut self) -> impl Iterator<Item = &mut AskField> {
+        self.fields.iter_mut().filter(|field| field.is_question())
+    }
+
+    /// Encode the answers as the data block sent after a `selector\t+\t1`
+    /// request: one line per answer, with `AskL` answers preceded by their
+    /// number of lines
+    ///
+    /// The block is ended by a line holding a single `.`, so answer lines
+    /// starting with a `.` have it doubled, as in text files.
+    pub fn data_block(&self) -> String {
+        fn push_line(block: &mut String, line: &str) {
+            if line.starts_with('.') {
+                block.push('.');
+            }
+            block.push_str(line);
+            block.push_str("\r\n");
+        }
+
+        let mut block = String::from("+-1\r\n");
+        for field in self.fields.iter().filter(|field| field.is_question()) {
+            if field.kind == AskKind::AskLong {
+                let lines: Vec<&str> = field.answer.lines().collect();
+                block.push_str(&format!("{}\r\n", lines.len()));
+                for line in lines {
+                    push_line(&mut block, line);
+                }
+            } else {
+                push_line(&mut block, &field.answer);
+            }
+        }
+        block.push_str(".\r\n");
+        block
+    }
+}
+
+impl std::str::FromStr for AskForm {
+    type Err = GopherError;
+    fn from_str(s: &str) -> Result<AskForm, GopherError> {
+        AskForm::from_str(s)
+    }
+}
+
+/// The attributes of a Gopher+ item
+#[derive(Clone, Debug)]
+pub struct ItemAttributes {
+    /// The item described by these attributes, from the `+INFO` block
+    pub item: DirectoryItem,
+    /// The administrator contact, from the `Admin:` line of `+ADMIN`
+    pub admin: Option<String>,
+    /// The modification date, from the `Mod-Date:` line of `+ADMIN`
+    pub mod_date: Option<String>,
+    /// The alternate views from `+VIEWS`
+    pub views: Vec<View>,
+    /// The text of the `+ABSTRACT` block, if any
+    pub abstract_text: Option<String>,
+    /// The form from the `+ASK` block, if any
+    pub ask: Option<AskForm>,
+    /// All of the attribute blocks, including any not handled above
+    pub blocks: Vec<AttributeBlock>,
+}
+
+impl ItemAttributes {
+    /// Parse the attribute blocks for a single item
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<ItemAttributes, GopherError> {
+        let mut records = parse_records(s)?;
+        if records.len() != 1 {
+            return Err(GopherError::ParseAttributes(s.into()));
+        }
+        Ok(records.remove(0))
+    }
+
+    /// Find the first view matching a preference such as `text/plain`
+    pub fn find_view(&self, preference: &str) -> Option<&View> {
+        self.views.iter().find(|view| view.matches(preference))
+    }
+
+    /// Choose a view from a list of preferences, in order of priority
+    ///
+    /// ```
+    /// use gopher::gopherplus::*;
+    ///
+    /// let attributes = ItemAttributes::from_str("+INFO: 0Paper\t/paper\tgopher.example.net\t70\t+
+    /// +VIEWS:
+    ///  application/postscript: <100k>
+    ///  text/plain De_DE: <12k>
+    ///  text/plain En_US: <10k>
+    /// ").expect("failed to parse attributes");
+    ///
+    /// let view = attributes.select_view(&["text/plain En_US", "text/*"]).unwrap();
+    /// assert_eq!(view.representation(), "text/plain En_US");
+    /// ```
+    pub fn select_view(&self, preferences: &[&str]) -> Option<&View> {
+        preferences.iter().filter_map(|preference| self.find_view(preference)).next()
+    }
+
+    /// Find a block by name, eg `ADMIN`
+    pub fn block(&self, name: &str) -> Option<&AttributeBlock> {
+        self.blocks.iter().find(|block| block.name == name)
+    }
+
+    fn from_blocks(blocks: Vec<AttributeBlock>) -> Result<ItemAttributes, GopherError> {
+        let item = match blocks.first() {
+            Some(info) if info.name == "INFO" => DirectoryItem::from_str(&info.value)?,
+            _ => return Err(GopherError::ParseAttributes("missing +INFO block".into())),
+        };
+
+        let mut attributes = ItemAttributes {
+            item,
+            admin: None,
+            mod_date: None,
+            views: Vec::new(),
+            abstract_text: None,
+            ask: None,
+            blocks: Vec::new(),
+        };
+
+        for block in &blocks {
+            match &*block.name {
+                "ADMIN" => for line in &block.lines {
+                    if let Some(admin) = line.strip_prefix("Admin:") {
+                        attributes.admin = Some(admin.trim().into());
+                    } else if let Some(date) = line.strip_prefix("Mod-Date:") {
+                        attributes.mod_date = Some(date.trim().into());
+                    }
+                },
+                "VIEWS" => for line in &block.lines {
+                    if !line.trim().is_empty() {
+                        attributes.views.push(View::from_str(line)?);
+                    }
+                },
+                "ABSTRACT" => {
+                    attributes.abstract_text = Some(block.lines.join("\n"));
+                },
+                "ASK" => {
+                    attributes.ask = Some(AskForm::from_str(&block.lines.join("\n"))?);
+                },
+                _ => {}
+            }
+        }
+
+        attributes.blocks = blocks;
+        Ok(attributes)
+    }
+}
+
+impl std::str::FromStr for ItemAttributes {
+    type Err = GopherError;
+    fn from_str(s: &str) -> Result<ItemAttributes, GopherError> {
+        ItemAttributes::from_str(s)
+    }
+}
+
+/// Split a list of attribute blocks into one record per `+INFO` block
+fn parse_records(s: &str) -> Result<Vec<ItemAttributes>, GopherError> {
+    let mut records = Vec::new();
+    let mut blocks: Vec<AttributeBlock> = Vec::new();
+
+    for line in s.lines() {
+        if line.trim() == "." { break; }
+
+        if let Some(header) = line.strip_prefix('+') {
+            let (name, value) = match header.find(':') {
+                Some(idx) => (&header[..idx], header[idx+1..].trim_start_matches(' ')),
+                None => (header.trim_end(), ""),
+            };
+
+            if name == "INFO" && !blocks.is_empty() {
+                records.push(ItemAttributes::from_blocks(blocks)?);
+                blocks = Vec::new();
+            }
+
+            blocks.push(AttributeBlock {
+                name: name.into(),
+                value: value.into(),
+                lines: Vec::new(),
+            });
+        } else if let Some(block) = blocks.last_mut() {
+            block.lines.push(line.strip_prefix(' ').unwrap_or(line).into());
+        } else if !line.trim().is_empty() {
+            return Err(GopherError::ParseAttributes(line.into()));
+        }
+    }
+
+    if !blocks.is_empty() {
+        records.push(ItemAttributes::from_blocks(blocks)?);
+    }
+    Ok(records)
+}
+
+/// Remove the Gopher+ response header (`+-1`, `+-2` or `+<length>`) from a
+/// server response, or report the error if the server sent one (`--<n>`)
+///
+/// Responses without a header are returned unchanged.
+pub fn strip_response_header(s: &str) -> Result<&str, GopherError> {
+    let Range { start, mut end } = response_body(s.as_bytes())?;
+    while !s.is_char_boundary(end) { end -= 1; }
+    Ok(&s[start..end])
+}
+
+/// Remove the Gopher+ response header from a response which may not be
+/// text, such as an image view
+pub fn strip_response_header_bytes(data: &[u8]) -> Result<&[u8], GopherError> {
+    response_body(data).map(|range| &data[range])
+}
+
+/// Find the body of a Gopher+ response, after the header
+fn response_body(data: &[u8]) -> Result<Range<usize>, GopherError> {
+    let (first, rest) = match data.iter().position(|&b| b == b'\n') {
+        Some(idx) => (&data[..idx], idx + 1),
+        None => (data, data.len()),
+    };
+    let first = first.strip_suffix(b"\r").unwrap_or(first);
+
+    if let Some(code) = first.strip_prefix(b"--") {
+        let message = String::from_utf8_lossy(&data[rest..]);
+        let message = message.trim();
+        return Err(GopherError::Server(
+            if message.is_empty() { String::from_utf8_lossy(code).into_owned() } else { message.into() }
+        ));
+    }
+
+    match first {
+        b"+-1" => {
+            // data is terminated by a lone period
+            let mut offset = rest;
+            for line in data[rest..].split(|&b| b == b'\n') {
+                if line.strip_suffix(b"\r").unwrap_or(line) == b"." {
+                    return Ok(rest..offset);
+                }
+                offset += line.len() + 1;
+            }
+            Ok(rest..data.len())
+        },
+        b"+-2" => Ok(rest..data.len()),
+        _ => match first.strip_prefix(b"+").and_then(|len| ::std::str::from_utf8(len).ok()).map(str::parse::<usize>) {
+            Some(Ok(len)) => Ok(rest..data.len().min(rest + len)),
+            _ => Ok(0..data.len()),
+        }
+    }
+}
+
+/// Connect to a Gopher+ server and request the attributes of an item
+#[deprecated(note = "use `Client::read_attributes`, which applies the client's limits")]
+pub fn read_attributes<T: ToSocketAddrs>(address: T, selector: &str) -> Result<ItemAttributes, GopherError> {
+    attributes_response(net::request(address, format!("{}\t!", selector).as_bytes(), b"")?)
+}
+
+/// Request the attributes of a directory item
+pub fn read_item_attributes(item: &DirectoryItem) -> Result<ItemAttributes, GopherError> {
+    Client::default().read_item_attributes(item)
+}
+
+/// Connect to a Gopher+ server and request the attributes of every item in
+/// a directory, with a `selector\t$` request
+#[deprecated(note = "use `Client::read_directory_attributes`, which applies the client's limits")]
+pub fn read_directory_attributes<T: ToSocketAddrs>(address: T, selector: &str) -> Result<Vec<ItemAttributes>, GopherError> {
+    directory_attributes_response(net::request(address, format!("{}\t$", selector).as_bytes(), b"")?)
+}
+
+/// Connect to a Gopher+ server and fetch a specific view of an item, with a
+/// `selector\t+representation` request
+///
+/// Text views are decoded, and any others are returned as bytes.
+#[deprecated(note = "use `Client::read_view`, which applies the client's limits")]
+pub fn read_view<T: ToSocketAddrs>(address: T, selector: &str, view: &View) -> Result<ViewData, GopherError> {
+    let buffer = net::request(address, format!("{}\t+{}", selector, view.representation()).as_bytes(), b"")?;
+    Ok(ViewData::new(view, strip_response_header_bytes(&buffer)?))
+}
+
+/// Fetch a specific view of a directory item
+pub fn read_item_view(item: &DirectoryItem, view: &View) -> Result<ViewData, GopherError> {
+    Client::default().read_item_view(item, view)
+}
+
+/// Request the ASK form of a directory item, which should be marked with a
+/// `?` in its fifth field
+pub fn read_item_form(item: &DirectoryItem) -> Result<AskForm, GopherError> {
+    Client::default().read_item_form(item)
+}
+
+/// Connect to a Gopher+ server and submit the answers to an ASK form, with a
+/// `selector\t+\t1` request followed by the data block
+///
+/// Returns the server's response, with the Gopher+ header removed.
+#[deprecated(note = "use `Client::submit_form`, which applies the client's limits")]
+pub fn submit_form<T: ToSocketAddrs>(address: T, selector: &str, form: &AskForm) -> Result<String, GopherError> {
+    let selector = format!("{}\t+\t1", selector);
+    form_response(net::request(address, selector.as_bytes(), form.data_block().as_bytes())?)
+}
+
+/// Submit the answers to the ASK form of a directory item
+pub fn submit_item_form(item: &DirectoryItem, form: &AskForm) -> Result<String, GopherError> {
+    Client::default().submit_item_form(item, form)
+}
+
+impl Client {
+    /// Request the attributes of a Gopher+ item, with a `selector\t!`
+    /// request
+    pub fn read_attributes(&self, host: &str, port: u16, selector: &str) -> Result<ItemAttributes, GopherError> {
+        attributes_response(self.request(host, port, format!("{}\t!", selector).as_bytes(), b"")?)
+    }
+
+    /// Request the attributes of a directory item
+    pub fn read_item_attributes(&self, item: &DirectoryItem) -> Result<ItemAttributes, GopherError> {
+        self.read_attributes(&item.host, item.port, &item.selector)
+    }
+
+    /// Request the attributes of every item in a Gopher+ directory, with a
+    /// `selector\t$` request
+    pub fn read_directory_attributes(&self, host: &str, port: u16, selector: &str) -> Result<Vec<ItemAttributes>, GopherError> {
+        directory_attributes_response(self.request(host, port, format!("{}\t$", selector).as_bytes(), b"")?)
+    }
+
+    /// Fetch a specific view of a Gopher+ item, with a
+    /// `selector\t+representation` request
+    ///
+    /// Text views are decoded, and any others are returned as bytes.
+    pub fn read_view(&self, host: &str, port: u16, selector: &str, view: &View) -> Result<ViewData, GopherError> {
+        let selector = format!("{}\t+{}", selector, view.representation());
+        let buffer = self.request(host, port, selector.as_bytes(), b"")?;
+        Ok(ViewData::new(view, strip_response_header_bytes(&buffer)?))
+    }
+
+    /// Fetch a specific view of a directory item
+    pub fn read_item_view(&self, item: &DirectoryItem, view: &View) -> Result<ViewData, GopherError> {
+        self.read_view(&item.host, item.port, &item.selector, view)
+    }
+
+    /// Request the ASK form of a directory item
+    pub fn read_item_form(&self, item: &DirectoryItem) -> Result<AskForm, GopherError> {
+        self.read_item_attributes(item)?.ask
+            .ok_or_else(|| GopherError::ParseAttributes("missing +ASK block".into()))
+    }
+
+    /// Submit the answers to an ASK form, with a `selector\t+\t1` request
+    /// followed by the data block
+    ///
+    /// Returns the server's response, with the Gopher+ header removed.
+    pub fn submit_form(&self, host: &str, port: u16, selector: &str, form: &AskForm) -> Result<String, GopherError> {
+        let selector = format!("{}\t+\t1", selector);
+        form_response(self.request(host, port, selector.as_bytes(), form.data_block().as_bytes())?)
+    }
+
+    /// Submit the answers to the ASK form of a directory item
+    pub fn submit_item_form(&self, item: &DirectoryItem, form: &AskForm) -> Result<String, GopherError> {
+        self.submit_form(&item.host, item.port, &item.selector, form)
+    }
+}
+
+/// Parse the response to a `selector\t!` request
+fn attributes_response(buffer: Vec<u8>) -> Result<ItemAttributes, GopherError> {
+    ItemAttributes::from_str(strip_response_header(&net::utf8(buffer)?)?)
+}
+
+/// Parse the response to a `selector\t$` request
+fn directory_attributes_response(buffer: Vec<u8>) -> Result<Vec<ItemAttributes>, GopherError> {
+    parse_records(strip_response_header(&net::utf8(buffer)?)?)
+}
+
+/// Decode the response to a submitted form, removing the Gopher+ header
+fn form_response(buffer: Vec<u8>) -> Result<String, GopherError> {
+    strip_response_header(&net::utf8(buffer)?).map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Type;
+
+    #[test]
+    fn parse_attributes() {
+        let input = "+-1\r
++INFO: 0About this server\t/about\tgopher.example.net\t70\t+\r
++ADMIN:\r
+ Admin: Gopher Admin <admin@example.net>\r
+ Mod-Date: Wed Jul 28 17:02:01 1993 <19930728170201>\r
++VIEWS:\r
+ text/plain: <1k>\r
+ text/plain De_DE: <2k>\r
+ application/postscript: <100k>\r
++ABSTRACT:\r
+ A short description\r
+ of this server.\r
++VENDOR: something\r
+.\r
+";
+        let attributes = ItemAttributes::from_str(strip_response_header(input).unwrap())
+            .expect("failed to parse sample attributes");
+
+        assert_eq!(attributes.item.t, Type::File);
+        assert_eq!(attributes.item.selector, "/about");
+        assert!(attributes.item.is_gopher_plus());
+        assert_eq!(attributes.admin, Some(String::from("Gopher Admin <admin@example.net>")));
+        assert_eq!(attributes.mod_date, Some(String::from("Wed Jul 28 17:02:01 1993 <19930728170201>")));
+        assert_eq!(attributes.views, vec![
+            View { mime: "text/plain".into(), language: None, size: Some("1k".into()) },
+            View { mime: "text/plain".into(), language: Some("De_DE".into()), size: Some("2k".into()) },
+            View { mime: "application/postscript".into(), language: None, size: Some("100k".into()) },
+        ]);
+        assert_eq!(attributes.abstract_text, Some(String::from("A short description\nof this server.")));
+        assert_eq!(attributes.block("VENDOR").map(|b| &*b.value), Some("something"));
+    }
+
+    #[test]
+    fn response_headers() {
+        assert_eq!(strip_response_header("+5\r\nhello world").unwrap(), "hello");
+        assert_eq!(strip_response_header("+-2\r\nhello world").unwrap(), "hello world");
+        assert_eq!(strip_response_header("no header").unwrap(), "no header");
+        match strip_response_header("--1\r\n1 Gopher Admin <admin@example.net>\r\n") {
+            Err(GopherError::Server(message)) => assert_eq!(message, "1 Gopher Admin <admin@example.net>"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(strip_response_header("+-1\r\nline\r\n.\r\nafter").unwrap(), "line\r\n");
+        assert_eq!(strip_response_header("+3\r\ncafé").unwrap(), "caf");
+        assert_eq!(strip_response_header_bytes(b"+4\r\nGIF89a").unwrap(), b"GIF8");
+    }
+
+    #[test]
+    fn read_views() {
+        let port = net::tests::serve(vec![
+            b"+-2\r\nGIF89a\xff\x00".to_vec(),
+            b"+-1\r\nCaf\xe9\r\n.\r\n".to_vec(),
+        ]);
+        let item = DirectoryItem::from_str(&format!("0Picture\t/picture\t127.0.0.1\t{}\t+", port)).unwrap();
+
+        let gif = View::from_str("image/gif: <1k>").unwrap();
+        assert_eq!(read_item_view(&item, &gif).unwrap(), ViewData::Binary(b"GIF89a\xff\x00".to_vec()));
+        let text = View::from_str("text/plain: <1k>").unwrap();
+        assert_eq!(Client::new().read_item_view(&item, &text).unwrap(), ViewData::Text("Café\r\n".into()));
+    }
+
+    #[test]
+    fn select_views() {
+        let input = "+INFO: 1Papers\t/papers\tgopher.example.net\t70\t+
++VIEWS:
+ application/gopher+-menu: <1k>
++INFO: 0Paper\t/papers/paper\tgopher.example.net\t70\t+
++VIEWS:
+ application/postscript: <100k>
+ Text/Plain De_DE: <12k>
+ text/plain En_US: <10k>
+";
+        let records = parse_records(input).expect("failed to parse directory attributes");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].item.selector, "/papers");
+
+        let paper = &records[1];
+        assert_eq!(paper.find_view("text/plain").map(View::representation),
+                   Some(String::from("Text/Plain De_DE")));
+        assert_eq!(paper.select_view(&["text/plain en_us", "application/postscript"]).map(View::representation),
+                   Some(String::from("text/plain En_US")));
+        assert_eq!(paper.select_view(&["image/*", "application/*"]).map(View::representation),
+                   Some(String::from("application/postscript")));
+        assert!(paper.select_view(&["image/gif"]).is_none());
+    }
+
+    #[test]
+    fn parse_ask_form() {
+        let input = "+INFO: 0Survey\t/survey\tgopher.example.net\t70\t?
++ASK:
+ Note: Please answer a few questions
+ Ask: Your name?\tAnonymous
+ AskP: Password
+ AskL: Comments
+ Choose: Colour\tred\tgreen\tblue
+ Select: Subscribe?\t0
+";
+        let mut attributes = ItemAttributes::from_str(input).expect("failed to parse sample attributes");
+        assert!(attributes.item.has_ask_form());
+
+        let form = attributes.ask.as_mut().expect("missing ask form");
+        let kinds: Vec<AskKind> = form.fields.iter().map(|f| f.kind.clone()).collect();
+        assert_eq!(kinds, vec![AskKind::Note, AskKind::Ask, AskKind::AskPassword,
+                               AskKind::AskLong, AskKind::Choose, AskKind::Select]);
+        assert_eq!(form.fields[1].answer, "Anonymous");
+        assert_eq!(form.fields[4].options, vec!["red", "green", "blue"]);
+        assert_eq!(form.fields[4].answer, "red");
+
+        assert!(form.fields[1].set_answer("a\tb").is_err());
+        assert!(form.fields[4].set_answer("purple").is_err());
+        assert!(form.fields[5].set_answer("yes").is_err());
+
+        form.fields[2].set_answer("hunter2").unwrap();
+        form.fields[3].set_answer("first line\nsecond line").unwrap();
+        form.fields[4].set_answer("blue").unwrap();
+        form.fields[5].set_answer("1").unwrap();
+
+        assert_eq!(form.questions_mut().count(), 5);
+        assert_eq!(form.data_block(),
+                   "+-1\r\nAnonymous\r\nhunter2\r\n2\r\nfirst line\r\nsecond line\r\nblue\r\n1\r\n.\r\n");
+
+        form.fields[1].set_answer(".hidden").unwrap();
+        form.fields[3].set_answer("first line\n.\n..").unwrap();
+        assert_eq!(form.data_block(),
+                   "+-1\r\n..hidden\r\nhunter2\r\n3\r\nfirst line\r\n..\r\n...\r\nblue\r\n1\r\n.\r\n");
+    }
+
+    #[test]
+    fn parse_unknown_ask_fields() {
+        let input = "+INFO: 0Upload\t/upload\tgopher.example.net\t70\t?
++ASK:
+ Ask: Title\tUntitled
+ AskF: File to upload
+ ChooseF: Or an existing file\ta.txt\tb.txt
+ Select: Public?\t1
++ABSTRACT:
+ Upload a file.
+";
+        let attributes = ItemAttributes::from_str(input).expect("failed to parse attributes with unknown fields");
+        assert_eq!(attributes.abstract_text, Some(String::from("Upload a file.")));
+
+        let mut form = attributes.ask.expect("missing ask form");
+        assert_eq!(form.fields[1].kind, AskKind::Other("AskF".into()));
+        assert_eq!(form.fields[2].kind.as_keyword(), "ChooseF");
+        assert_eq!(form.fields[2].answer, "a.txt");
+        form.fields[1].set_answer("notes.txt").unwrap();
+        assert_eq!(form.questions_mut().count(), 4);
+        assert_eq!(form.data_block(), "+-1\r\nUntitled\r\nnotes.txt\r\na.txt\r\n1\r\n.\r\n");
+
+        assert!(AskField::from_str("Not a field: at all").is_err());
+    }
+}
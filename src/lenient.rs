@@ -0,0 +1,257 @@
+//! Lenient Directory Parsing
+//!
+//! Plenty of servers send menus which aren't quite valid: info lines without
+//! any tabs, missing ports, trailing whitespace and so on.  The strict parser
+//! rejects the whole directory when it finds one of these, while the lenient
+//! parser recovers what it can and reports what it had to fix up as a list of
+//! `Diagnostic`s.
+
+use std::fmt;
+
+use Type;
+use DirectoryItem;
+use Directory;
+use DirectoryReader;
+
+/// The kinds of problem the lenient parser can recover from
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DiagnosticKind {
+    /// The line had no tabs, and was treated as an info line
+    MissingTabs,
+    /// The line started with a tab, and was treated as an info line
+    MissingType,
+    /// The host field was missing, and was left empty
+    MissingHost,
+    /// The port field was missing, and defaulted to 70
+    MissingPort,
+    /// The port field was not a valid port number, and defaulted to 70
+    BadPort(String),
+    /// The line had trailing whitespace, which was removed
+    TrailingWhitespace,
+    /// The line was empty, and was skipped
+    EmptyLine,
+    /// There was more content after the `.` terminator, which was ignored
+    ContentAfterTerminator,
+    /// The line was not valid UTF-8, and invalid bytes were replaced with
+    /// U+FFFD
+    InvalidUtf8,
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DiagnosticKind::MissingTabs => write!(f, "no tabs, treated as info line"),
+            DiagnosticKind::MissingType => write!(f, "no type, treated as info line"),
+            DiagnosticKind::MissingHost => write!(f, "missing host"),
+            DiagnosticKind::MissingPort => write!(f, "missing port, defaulted to 70"),
+            DiagnosticKind::BadPort(ref port) => write!(f, "bad port {:?}, defaulted to 70", port),
+            DiagnosticKind::TrailingWhitespace => write!(f, "trailing whitespace removed"),
+            DiagnosticKind::EmptyLine => write!(f, "empty line skipped"),
+            DiagnosticKind::ContentAfterTerminator => write!(f, "content after terminator ignored"),
+            DiagnosticKind::InvalidUtf8 => write!(f, "invalid UTF-8 replaced"),
+        }
+    }
+}
+
+/// A problem found and recovered from while parsing a directory
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    /// The line number, starting from 1
+    pub line: usize,
+    pub kind: DiagnosticKind,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+/// Parse a single line of a directory, recovering from any problems
+///
+/// Returns None if the line should be skipped.
+pub(crate) fn parse_line(line: &str, line_number: usize, diagnostics: &mut Vec<Diagnostic>) -> Option<DirectoryItem> {
+    let mut report = |kind| diagnostics.push(Diagnostic { line: line_number, kind });
+
+    let trimmed = line.trim_end_matches(|c: char| c != '\t' && c.is_whitespace());
+    if trimmed.len() != line.len() {
+        report(DiagnosticKind::TrailingWhitespace);
+    }
+    let line = trimmed;
+
+    if line.is_empty() {
+        report(DiagnosticKind::EmptyLine);
+        return None;
+    }
+
+    let info = |name: &str| DirectoryItem {
+        t: Type::Info,
+        name: name.into(),
+        selector: String::new(),
+        host: String::new(),
+        port: 0,
+        extra: Vec::new(),
+    };
+
+    if !line.contains('\t') {
+        report(DiagnosticKind::MissingTabs);
+        return Some(info(line));
+    }
+
+    let mut fields = line.split('\t');
+    let first = fields.next().unwrap_or("");
+    let mut chars = first.chars();
+    let t = match chars.next() {
+        Some(c) => Type::from_char(c),
+        None => {
+            report(DiagnosticKind::MissingType);
+            return Some(info(line.trim()));
+        }
+    };
+    let name = chars.as_str();
+    let selector = fields.next().unwrap_or("");
+
+    let host = match fields.next() {
+        Some(host) => host,
+        None => {
+            report(DiagnosticKind::MissingHost);
+            ""
+        }
+    };
+
+    let port = match fields.next().map(str::trim) {
+        Some("") => 70,
+        Some(port) => match port.parse() {
+            Ok(port) => port,
+            Err(_) => {
+                report(DiagnosticKind::BadPort(port.into()));
+                70
+            }
+        },
+        None => {
+            report(DiagnosticKind::MissingPort);
+            70
+        }
+    };
+
+    Some(DirectoryItem {
+        t,
+        name: name.into(),
+        selector: selector.into(),
+        host: host.into(),
+        port,
+        extra: fields.map(String::from).collect(),
+    })
+}
+
+impl Directory {
+    /// Parse a &str into a Directory, recovering from malformed lines instead
+    /// of failing
+    ///
+    /// ```
+    /// use gopher::*;
+    ///
+    /// let input = "Welcome to my gopherhole!
+    /// 0About\t/about.txt\tgopher.example.net
+    /// 1Files\t/files\tgopher.example.net\t70
+    /// .";
+    ///
+    /// let (directory, diagnostics) = Directory::from_str_lenient(input);
+    /// assert_eq!(directory.items().len(), 3);
+    /// assert_eq!(directory.items()[0].t, Type::Info);
+    /// assert_eq!(directory.items()[1].port, 70);
+    /// assert_eq!(diagnostics[0].to_string(), "line 1: no tabs, treated as info line");
+    /// assert_eq!(diagnostics[1].to_string(), "line 2: missing port, defaulted to 70");
+    /// ```
+    pub fn from_str_lenient(s: &str) -> (Directory, Vec<Diagnostic>) {
+        Directory::from_bytes_lenient(s.as_bytes())
+    }
+
+    /// Parse bytes into a Directory, recovering from malformed lines instead
+    /// of failing
+    ///
+    /// Lines which aren't valid UTF-8 are decoded lossily, with an
+    /// `InvalidUtf8` diagnostic, rather than ending the directory.
+    pub fn from_bytes_lenient(bytes: &[u8]) -> (Directory, Vec<Diagnostic>) {
+        let mut reader = DirectoryReader::new(bytes).lenient();
+        let items = reader.by_ref().filter_map(Result::ok).collect();
+        let line = reader.line_number();
+        let mut diagnostics = reader.take_diagnostics();
+
+        if reader.into_inner().iter().any(|b| !b.is_ascii_whitespace()) {
+            diagnostics.push(Diagnostic { line: line + 1, kind: DiagnosticKind::ContentAfterTerminator });
+        }
+
+        (Directory { items }, diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_lenient() {
+        let input = "Plain text line
+iProper info line\tfake\t(NULL)\t0
+0Missing host\t/file.txt
+1Bad port\t/dir\tgopher.example.net\tseventy
+1Trailing whitespace\t/dir\tgopher.example.net\t70\x20\x20
+
+\tno type\t\t
+0Gopher+\t/plus\tgopher.example.net\t70\t+
+.
+1After the end\t/\tgopher.example.net\t70
+";
+        let (directory, diagnostics) = Directory::from_str_lenient(input);
+        let items = directory.items();
+        assert_eq!(items.len(), 7);
+
+        assert_eq!(items[0].t, Type::Info);
+        assert_eq!(items[0].name, "Plain text line");
+        assert_eq!(items[1].name, "Proper info line");
+        assert_eq!(items[2].host, "");
+        assert_eq!(items[2].port, 70);
+        assert_eq!(items[3].port, 70);
+        assert_eq!(items[4].port, 70);
+        assert_eq!(items[5].t, Type::Info);
+        assert_eq!(items[5].name, "no type");
+        assert!(items[6].is_gopher_plus());
+
+        assert_eq!(diagnostics, vec![
+            Diagnostic { line: 1, kind: DiagnosticKind::MissingTabs },
+            Diagnostic { line: 3, kind: DiagnosticKind::MissingHost },
+            Diagnostic { line: 3, kind: DiagnosticKind::MissingPort },
+            Diagnostic { line: 4, kind: DiagnosticKind::BadPort("seventy".into()) },
+            Diagnostic { line: 5, kind: DiagnosticKind::TrailingWhitespace },
+            Diagnostic { line: 6, kind: DiagnosticKind::EmptyLine },
+            Diagnostic { line: 7, kind: DiagnosticKind::MissingType },
+            Diagnostic { line: 10, kind: DiagnosticKind::ContentAfterTerminator },
+        ]);
+    }
+
+    #[test]
+    fn parse_lenient_non_utf8() {
+        let input: &[u8] = b"iWelcome\tfake\t(NULL)\t0
+0Caf\xe9\t/cafe\tgopher.example.net\t70
+1After\t/after\tgopher.example.net\t70
+.";
+        let (directory, diagnostics) = Directory::from_bytes_lenient(input);
+        let items = directory.items();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[1].name, "Caf\u{fffd}");
+        assert_eq!(items[1].selector, "/cafe");
+        assert_eq!(items[2].name, "After");
+        assert_eq!(diagnostics, vec![Diagnostic { line: 2, kind: DiagnosticKind::InvalidUtf8 }]);
+    }
+
+    #[test]
+    fn parse_lenient_valid() {
+        let input = "0About internet Gopher\tStuff:About us\trawBits.micro.umn.edu\t70
+1Courses, Schedules, Calendars\t\tevents.ais.umn.edu\t9120
+.";
+        let (directory, diagnostics) = Directory::from_str_lenient(input);
+        assert!(diagnostics.is_empty());
+        assert_eq!(format!("{}", directory), input);
+    }
+}
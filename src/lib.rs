@@ -25,11 +25,11 @@
 //! # Examples
 //!
 //! This library includes as an example a simple command-line gopher client,
-//! which can be run with `cargo run --example client` or `cargo run --example client -- hostname:port resource`.
+//! which can be run with `cargo run --example client` or `cargo run --example client -- gopher://hostname:port/1resource`.
 //!
 //! `
-//! $ cargo run --example client -- cargo run --example client -- gopher.quux.org:70 /Software/Gopher/servers
-//!     Running `target/debug/examples/client gopher.quux.org:70 /Software/Gopher/servers`
+//! $ cargo run --example client -- gopher://gopher.quux.org/1/Software/Gopher/servers
+//!     Running `target/debug/examples/client gopher://gopher.quux.org/1/Software/Gopher/servers`
 //! Got Directory:
 //! 
 //! 1 Aerv.nl                                                      aerv.nl:70
@@ -50,31 +50,57 @@
 //! 1 xn--ortsvernderlich-6kb.de                                   gopher.xn--ortsvernderlich-6kb.de:70
 //! $
 //! `
+//!
+//! # Features
+//!
+//! - `serde`: `Serialize` and `Deserialize` for `Directory`, `DirectoryItem`
+//!   and `Type`, which is written as its item character.
 
 #[macro_use] extern crate lazy_static;
 extern crate regex;
+extern crate encoding_rs;
+extern crate idna;
+extern crate socket2;
+#[cfg(feature = "serde")]
+#[macro_use] extern crate serde;
 
-use std::io;
+use std::io::BufRead;
 use std::fmt;
 use regex::Regex;
 
 pub mod net;
+pub mod gopherplus;
+pub mod gophermap;
+pub mod render;
+mod error;
+mod reader;
+mod lenient;
+mod raw;
+mod builder;
+mod borrowed;
+mod text;
+mod diff;
+mod query;
+mod normalize;
+#[cfg(feature = "serde")]
+mod serde_impls;
 
-#[derive(Debug)]
-pub enum GopherError {
-    Io(io::Error),
-    ParseDirectoryItem(String),
-    ParseDirectory(String),
-}
-
-impl From<io::Error> for GopherError {
-    fn from(io: io::Error) -> GopherError {
-        GopherError::Io(io)
-    }
-}
+pub use error::{GopherError, Position};
+pub use reader::DirectoryReader;
+pub use lenient::{Diagnostic, DiagnosticKind};
+pub use raw::{Charset, RawDirectory, RawDirectoryItem};
+pub use builder::{DirectoryBuilder, Violation, ViolationKind, MAX_SELECTOR_LENGTH};
+pub use borrowed::{DirectoryRef, DirectoryItemRef};
+pub use text::TextDocument;
+pub use diff::{Change, DirectoryDiff};
+pub use query::Section;
+pub use normalize::normalize_host;
 
 /// Possible types of Gopher directory items
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+///
+/// This covers the types from RFC 1436, along with the de-facto types in
+/// common use since.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum Type {
     File,
     Directory,
@@ -91,24 +117,95 @@ pub enum Type {
     GIF,
     Image,
     Info,
+    Html,
+    Sound,
+    Document,
+    PNG,
+    Video,
+    Calendar,
+    MIME,
+    PDF,
+    RTF,
+    XML,
     Unknown(char),
 }
 
+/// Broad categories of item types, for deciding how to handle an item
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TypeCategory {
+    /// Readable text, such as plain text files and HTML
+    Text,
+    /// Gopher directories
+    Menu,
+    /// Items which need input from the user, such as search servers and
+    /// telnet sessions
+    Interactive,
+    /// Files to be downloaded, such as archives and documents
+    Binary,
+    /// Images, sound and video
+    Media,
+    /// Items which aren't links at all, such as info lines and errors
+    Informational,
+}
+
 impl Type {
     /// Utility function for identifying directory items
     pub fn is_directory(&self) -> bool {
         *self == Type::Directory
     }
 
-    /// Utility function for identifying plain file items
+    /// Utility function for identifying plain file items, which can be
+    /// fetched and saved or displayed as they are
     pub fn is_file(&self) -> bool {
+        matches!(self.category(), TypeCategory::Text | TypeCategory::Binary | TypeCategory::Media)
+    }
+
+    /// The broad category of this type
+    ///
+    /// Unknown types are assumed to be binary files.
+    pub fn category(&self) -> TypeCategory {
+        match *self {
+            Type::File | Type::Html | Type::Calendar |
+            Type::MIME | Type::XML
+                => TypeCategory::Text,
+            Type::Directory | Type::RedundantServer
+                => TypeCategory::Menu,
+            Type::CSOPhoneBook | Type::SearchServer |
+            Type::TelnetSession | Type::Tn3270Session
+                => TypeCategory::Interactive,
+            Type::BinHexed | Type::BinArchive | Type::UUEncoded |
+            Type::Binary | Type::Document | Type::PDF | Type::RTF |
+            Type::Unknown(_)
+                => TypeCategory::Binary,
+            Type::GIF | Type::Image | Type::PNG | Type::Sound | Type::Video
+                => TypeCategory::Media,
+            Type::Error | Type::Info
+                => TypeCategory::Informational,
+        }
+    }
+
+    /// The MIME type of the content of this type of item, if known
+    ///
+    /// Generic media types give a wildcard, such as `image/*` for `I`.
+    pub fn mime_type(&self) -> Option<&'static str> {
         match *self {
-            Type::Directory | Type::CSOPhoneBook |
-            Type::Error | Type::SearchServer |
-            Type::TelnetSession | Type::Tn3270Session |
-            Type::RedundantServer | Type::Unknown(_)
-                => true,
-            _ => false,
+            Type::File => Some("text/plain"),
+            Type::Directory => Some("application/gopher-menu"),
+            Type::BinHexed => Some("application/mac-binhex40"),
+            Type::BinArchive | Type::Binary => Some("application/octet-stream"),
+            Type::UUEncoded => Some("text/x-uuencode"),
+            Type::GIF => Some("image/gif"),
+            Type::Image => Some("image/*"),
+            Type::Html => Some("text/html"),
+            Type::Sound => Some("audio/*"),
+            Type::PNG => Some("image/png"),
+            Type::Video => Some("video/*"),
+            Type::Calendar => Some("text/calendar"),
+            Type::MIME => Some("message/rfc822"),
+            Type::PDF => Some("application/pdf"),
+            Type::RTF => Some("application/rtf"),
+            Type::XML => Some("application/xml"),
+            _ => None,
         }
     }
 
@@ -130,6 +227,16 @@ impl Type {
             'g' => Type::GIF,
             'I' => Type::Image,
             'i' => Type::Info,
+            'h' => Type::Html,
+            's' => Type::Sound,
+            'd' => Type::Document,
+            'p' => Type::PNG,
+            ';' => Type::Video,
+            'c' => Type::Calendar,
+            'M' => Type::MIME,
+            'P' => Type::PDF,
+            'r' => Type::RTF,
+            'X' => Type::XML,
             other => Type::Unknown(other)
         }
     }
@@ -152,47 +259,160 @@ impl Type {
             Type::GIF => 'g',
             Type::Image => 'I',
             Type::Info => 'i',
+            Type::Html => 'h',
+            Type::Sound => 's',
+            Type::Document => 'd',
+            Type::PNG => 'p',
+            Type::Video => ';',
+            Type::Calendar => 'c',
+            Type::MIME => 'M',
+            Type::PDF => 'P',
+            Type::RTF => 'r',
+            Type::XML => 'X',
             Type::Unknown(other) => other,
         }
     }
 }
 
 /// An item in a Gopher Directory
+///
+/// Items compare, hash and sort by where they link to rather than by every
+/// field: links are equal when their types, selectors and ports match and
+/// their hosts match after `normalize_host`, whatever their names and extra
+/// fields, and info items are equal when their text matches.  Compare the
+/// fields directly to tell a renamed link from the original.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DirectoryItem {
     pub t: Type,
     pub name: String,
     pub selector: String,
     pub host: String,
     pub port: u16,
+    /// Any tab-separated fields following the port, such as the Gopher+
+    /// `+` marker
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub extra: Vec<String>,
 }
 
 impl DirectoryItem {
     /// Parse a &str into a DirectoryItem
+    ///
+    /// Errors are reported as if the item was on line 1; use
+    /// `GopherError::at_line` to correct the position.
     pub fn from_str(s: &str) -> Result<DirectoryItem, GopherError> {
-        if s.len() <= 1 {
-            return Err(GopherError::ParseDirectoryItem(s.into()));
-        }
-
         lazy_static! {
-            static ref RE: Regex = Regex::new(r"(?P<t>.)(?P<name>[^\t]*)\t(?P<selector>[^\t]*)\t(?P<host>[^\t]*)\t(?P<port>\d*)").unwrap();
+            static ref RE: Regex = Regex::new(r"^(?P<t>.)(?P<name>[^\t]*)\t(?P<selector>[^\t]*)\t(?P<host>[^\t]*)\t(?P<port>[^\t]*)(?:\t(?P<extra>.*))?$").unwrap();
         }
 
+        let t = match s.chars().next() {
+            Some(c) if c.is_control() => {
+                return Err(GopherError::InvalidType { position: Position::new(1, 1), found: c });
+            },
+            Some(c) => Type::from_char(c),
+            None => {
+                return Err(GopherError::MissingField { position: Position::new(1, 1), field: "type" });
+            },
+        };
+
         if let Some(captures) = RE.captures(s) {
+            let port = captures.name("port").unwrap();
+            let port = match port.as_str().trim() {
+                "" => 70,
+                text => text.parse().map_err(|_| GopherError::BadPort {
+                    position: Position::new(1, s[..port.start()].chars().count() + 1),
+                    port: text.into(),
+                })?,
+            };
+
+            // keep any further tab-separated fields, such as the Gopher+ marker
+            let extra = match captures.name("extra") {
+                Some(extra) => extra.as_str().split('\t').map(String::from).collect(),
+                None => Vec::new(),
+            };
+
             Ok(
                 DirectoryItem {
-                    t: Type::from_char(s.chars().next().unwrap()),
+                    t,
                     name: captures.name("name").unwrap().as_str().into(),
                     selector: captures.name("selector").unwrap().as_str().into(),
                     host: captures.name("host").unwrap().as_str().into(),
-                    port: captures.name("port").unwrap().as_str().parse().unwrap_or(70),
+                    port,
+                    extra,
                 }
             )
         } else {
-            Err(GopherError::ParseDirectoryItem(s.into()))
+            // the regex only fails if there are too few tabs
+            let field = match s.matches('\t').count() {
+                0 => "selector",
+                1 => "host",
+                _ => "port",
+            };
+            let position = Position::new(1, s.chars().count() + 1);
+            Err(GopherError::MissingField { position, field })
+        }
+    }
+
+    /// Returns any fields following the port
+    pub fn extra_fields(&self) -> &[String] {
+        &self.extra
+    }
+
+    /// Check whether the server marked this item as supporting Gopher+, with
+    /// either a `+` or a `?` (for items with an ASK form) in the fifth field
+    pub fn is_gopher_plus(&self) -> bool {
+        match self.extra.first() {
+            Some(field) => field == "+" || field == "?",
+            None => false,
+        }
+    }
+
+    /// Check whether the server marked this item as having a Gopher+ ASK
+    /// form, with a `?` in the fifth field
+    pub fn has_ask_form(&self) -> bool {
+        self.extra.first().is_some_and(|field| field == "?")
+    }
+
+    /// Build an item linking outside of gopherspace, using the common
+    /// `hName\tURL:https://...` convention
+    ///
+    /// The host and port should be those of the server publishing the link,
+    /// so that clients which don't understand the convention can still fetch
+    /// the selector and get a redirect page back.
+    pub fn from_external_url(name: &str, url: &str, host: &str, port: u16) -> DirectoryItem {
+        DirectoryItem {
+            t: Type::Html,
+            name: name.into(),
+            selector: format!("URL:{}", url),
+            host: host.into(),
+            port,
+            extra: Vec::new(),
         }
     }
 
+    /// If this item uses a `URL:` selector to link outside of gopherspace,
+    /// return the external URL
+    ///
+    /// ```
+    /// use gopher::*;
+    ///
+    /// let item = DirectoryItem::from_str("hThe Web\tURL:https://example.net/\tgopher.example.net\t70").unwrap();
+    /// assert_eq!(item.external_url(), Some("https://example.net/"));
+    /// ```
+    pub fn external_url(&self) -> Option<&str> {
+        external_url(&self.selector)
+    }
+
+    /// Check whether this item links outside of gopherspace
+    pub fn is_external_url(&self) -> bool {
+        self.external_url().is_some()
+    }
+
+    /// Build a `gopher://` URL pointing at this item
+    pub fn url(&self) -> GopherUrl {
+        GopherUrl::from_item(self)
+    }
+
     /// Many Gopher servers use "fake" items to provide human readable text in
     /// directory listings.
     /// This function is a simple heuristic, and shouldn't really be relied upon
@@ -211,7 +431,11 @@ impl fmt::Display for DirectoryItem {
                 name = self.name,
                 selector = self.selector,
                 host = self.host,
-                port = self.port)
+                port = self.port)?;
+        for field in &self.extra {
+            write!(f, "\t{}", field)?;
+        }
+        Ok(())
     }
 }
 
@@ -222,8 +446,16 @@ impl std::str::FromStr for DirectoryItem {
     }
 }
 
+/// Extract the external URL from a `URL:` selector, which some servers
+/// prefix with a `/`
+pub(crate) fn external_url(selector: &str) -> Option<&str> {
+    selector.strip_prefix("URL:")
+        .or_else(|| selector.strip_prefix("/URL:"))
+}
+
 /// A Gopher Directory
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Directory {
     items: Vec<DirectoryItem>
 }
@@ -231,17 +463,13 @@ pub struct Directory {
 impl Directory {
     /// Parse a &str into a Directory
     pub fn from_str(s: &str) -> Result<Directory, GopherError> {
-        let mut items = Vec::new();
-        for line in s.lines() {
-            if line.trim() == "." { break; }
-            else if let Ok(item) = DirectoryItem::from_str(line) {
-                items.push(item);
-            } else {
-                return Err(GopherError::ParseDirectoryItem(line.into()));
-            }
+        Directory::from_reader(s.as_bytes())
+    }
 
-        }
-        Ok(Directory { items: items })
+    /// Read a Directory from a `BufRead`, stopping at the `.` terminator
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Directory, GopherError> {
+        let items = DirectoryReader::new(reader).collect::<Result<Vec<_>, _>>()?;
+        Ok(Directory { items })
     }
 
     /// Returns the list of all DirectoryItems, including info items
@@ -251,7 +479,7 @@ impl Directory {
 
     /// Get the nth non-info link item
     pub fn get_nth_link(&self, idx: usize) -> Option<&DirectoryItem> {
-        self.items.iter().filter(|&item| !item.is_info()).nth(idx)
+        self.links().nth(idx)
     }
 }
 
@@ -271,6 +499,253 @@ impl std::str::FromStr for Directory {
     }
 }
 
+/// A `gopher://` URL, as described in
+/// [RFC 4266](https://tools.ietf.org/html/rfc4266)
+///
+/// ```
+/// use gopher::*;
+///
+/// let url = GopherUrl::from_str("gopher://gopher.example.net:7070/0/notes/some%20file.txt")
+///     .expect("failed to parse url");
+/// assert_eq!(url.host, "gopher.example.net");
+/// assert_eq!(url.port, 7070);
+/// assert_eq!(url.t, Type::File);
+/// assert_eq!(url.selector, "/notes/some file.txt");
+/// assert_eq!(url.to_string(), "gopher://gopher.example.net:7070/0/notes/some%20file.txt");
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GopherUrl {
+    pub host: String,
+    pub port: u16,
+    pub t: Type,
+    pub selector: String,
+    pub search: Option<String>,
+    pub gopher_plus: Option<String>,
+}
+
+impl GopherUrl {
+    /// Parse a `gopher://host:port/<type><selector>%09<search>%09<gopher+>` URL
+    ///
+    /// The port defaults to 70, and a URL with an empty path refers to the
+    /// root directory of the server.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<GopherUrl, GopherError> {
+        const SCHEME: &str = "gopher://";
+        let err = || GopherError::ParseUrl(s.into());
+
+        if !s.get(..SCHEME.len()).is_some_and(|scheme| scheme.eq_ignore_ascii_case(SCHEME)) {
+            return Err(err());
+        }
+        let rest = &s[SCHEME.len()..];
+
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => (&rest[..idx], &rest[idx+1..]),
+            None => (rest, ""),
+        };
+
+        // IPv6 literals are wrapped in brackets, and may contain colons
+        let (host, port) = if authority.starts_with('[') {
+            let end = authority.find(']').ok_or_else(&err)?;
+            let port = &authority[end+1..];
+            if !port.is_empty() && !port.starts_with(':') {
+                return Err(err());
+            }
+            (&authority[1..end], port.trim_start_matches(':'))
+        } else {
+            match authority.rfind(':') {
+                Some(idx) => (&authority[..idx], &authority[idx+1..]),
+                None => (authority, ""),
+            }
+        };
+
+        if host.is_empty() {
+            return Err(err());
+        }
+        let port = if port.is_empty() { 70 } else { port.parse().map_err(|_| err())? };
+
+        let mut chars = path.chars();
+        let t = Type::from_char(chars.next().unwrap_or('1'));
+
+        let mut parts = split_url_tabs(chars.as_str()).into_iter();
+        let selector = percent_decode(parts.next().unwrap_or("")).ok_or_else(&err)?;
+        let search = match parts.next() {
+            Some(search) => Some(percent_decode(search).ok_or_else(&err)?),
+            None => None,
+        };
+        let gopher_plus = match parts.next() {
+            Some(plus) => Some(percent_decode(plus).ok_or_else(&err)?),
+            None => None,
+        };
+
+        Ok(GopherUrl {
+            host: host.into(),
+            port,
+            t,
+            selector,
+            search,
+            gopher_plus,
+        })
+    }
+
+    /// Build a URL pointing at a directory item
+    pub fn from_item(item: &DirectoryItem) -> GopherUrl {
+        GopherUrl {
+            host: item.host.clone(),
+            port: item.port,
+            t: item.t,
+            selector: item.selector.clone(),
+            search: None,
+            gopher_plus: None,
+        }
+    }
+
+    /// Return a copy of this URL which sends a search query, for type 7
+    /// search servers
+    ///
+    /// The query is sent after a tab on the request line, so a query
+    /// containing a tab or line break is rejected with
+    /// `GopherError::InvalidQuery`.
+    ///
+    /// ```
+    /// use gopher::*;
+    ///
+    /// let url = GopherUrl::from_str("gopher://gopher.floodgap.com/7/v2/vs").unwrap()
+    ///     .with_search("gopher clients")
+    ///     .unwrap();
+    /// assert_eq!(url.to_string(), "gopher://gopher.floodgap.com/7/v2/vs%09gopher%20clients");
+    /// assert_eq!(url.request(), "/v2/vs\tgopher clients");
+    /// ```
+    pub fn with_search(mut self, query: &str) -> Result<GopherUrl, GopherError> {
+        if query.contains(&['\t', '\r', '\n'][..]) {
+            return Err(GopherError::InvalidQuery(query.into()));
+        }
+        self.search = Some(query.into());
+        Ok(self)
+    }
+
+    /// Convert into a directory item with the given display name
+    ///
+    /// The search and Gopher+ parts of the URL are not part of the item; use
+    /// `request()` to get the full string to send to the server.
+    pub fn to_item(&self, name: &str) -> DirectoryItem {
+        DirectoryItem {
+            t: self.t,
+            name: name.into(),
+            selector: self.selector.clone(),
+            host: self.host.clone(),
+            port: self.port,
+            extra: Vec::new(),
+        }
+    }
+
+    /// The request line to send to the server, without the trailing CRLF
+    pub fn request(&self) -> String {
+        let mut request = self.selector.clone();
+        if self.search.is_some() || self.gopher_plus.is_some() {
+            request.push('\t');
+            request.push_str(self.search.as_deref().unwrap_or(""));
+        }
+        if let Some(ref plus) = self.gopher_plus {
+            request.push('\t');
+            request.push_str(plus);
+        }
+        request
+    }
+}
+
+impl fmt::Display for GopherUrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "gopher://[{}]", self.host)?;
+        } else {
+            write!(f, "gopher://{}", self.host)?;
+        }
+        if self.port != 70 {
+            write!(f, ":{}", self.port)?;
+        }
+        write!(f, "/{}{}", self.t.as_char(), percent_encode(&self.selector))?;
+        if self.search.is_some() || self.gopher_plus.is_some() {
+            let search = self.search.as_deref().unwrap_or("");
+            write!(f, "%09{}", percent_encode(search))?;
+        }
+        if let Some(ref plus) = self.gopher_plus {
+            write!(f, "%09{}", percent_encode(plus))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for GopherUrl {
+    type Err = GopherError;
+    fn from_str(s: &str) -> Result<GopherUrl, GopherError> {
+        GopherUrl::from_str(s)
+    }
+}
+
+/// Split the path of a gopher URL on encoded tabs, into at most three parts
+fn split_url_tabs(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i + 3 <= bytes.len() && parts.len() < 2 {
+        if bytes[i] == b'%' && bytes[i+1] == b'0' && bytes[i+2] == b'9' {
+            parts.push(&s[start..i]);
+            start = i + 3;
+            i = start;
+        } else {
+            i += 1;
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Decode `%XX` escapes, returning None if the result is not valid UTF-8
+fn percent_decode(s: &str) -> Option<String> {
+    fn hex(b: u8) -> Option<u8> {
+        match b {
+            b'0'..=b'9' => Some(b - b'0'),
+            b'a'..=b'f' => Some(b - b'a' + 10),
+            b'A'..=b'F' => Some(b - b'A' + 10),
+            _ => None,
+        }
+    }
+
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex(bytes[i+1]), hex(bytes[i+2])) {
+                out.push(hi << 4 | lo);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Escape everything but unreserved characters, sub-delimiters, `:`, `@`
+/// and `/`
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for &b in s.as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' |
+            b'-' | b'.' | b'_' | b'~' | b'!' | b'$' | b'&' | b'\'' |
+            b'(' | b')' | b'*' | b'+' | b',' | b';' | b'=' | b':' |
+            b'@' | b'/'
+                => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -335,8 +810,145 @@ mod tests {
             selector: String::from("/sample.txt"),
             host: String::from("gopher.example.net"),
             port: 70,
+            extra: Vec::new(),
         };
         let output = format!("{}",item);
         assert_eq!(output, "0A Sample Text File\t/sample.txt\tgopher.example.net\t70");
     }
+
+    #[test]
+    fn parse_url() {
+        let url: GopherUrl = "gopher://[::1]:7070/7/search%20here%09some%20query".parse()
+            .expect("failed to parse sample url");
+        assert_eq!(url.host, "::1");
+        assert_eq!(url.port, 7070);
+        assert_eq!(url.t, Type::SearchServer);
+        assert_eq!(url.selector, "/search here");
+        assert_eq!(url.search, Some(String::from("some query")));
+        assert_eq!(url.gopher_plus, None);
+        assert_eq!(url.request(), "/search here\tsome query");
+
+        let root: GopherUrl = "gopher://gopher.example.net".parse().expect("failed to parse root url");
+        assert_eq!(root.port, 70);
+        assert_eq!(root.t, Type::Directory);
+        assert_eq!(root.selector, "");
+
+        assert!(GopherUrl::from_str("http://gopher.example.net/").is_err());
+        assert!(GopherUrl::from_str("gopher:/\u{e9}/1x").is_err());
+        assert!(GopherUrl::from_str("\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}").is_err());
+        assert!(GopherUrl::from_str("gopher://gopher.example.net:port/").is_err());
+        assert!(GopherUrl::from_str("gopher:///1/").is_err());
+    }
+
+    #[test]
+    fn format_url() {
+        let item = DirectoryItem {
+            t: Type::File,
+            name: String::from("A Sample Text File"),
+            selector: String::from("/sample file.txt"),
+            host: String::from("gopher.example.net"),
+            port: 70,
+            extra: Vec::new(),
+        };
+        let url = item.url();
+        assert_eq!(url.to_string(), "gopher://gopher.example.net/0/sample%20file.txt");
+
+        let back = url.to_item("A Sample Text File");
+        assert_eq!(format!("{}", back), format!("{}", item));
+
+        let mut plus = GopherUrl::from_str("gopher://[::1]/1").unwrap();
+        plus.gopher_plus = Some(String::from("!"));
+        assert_eq!(plus.to_string(), "gopher://[::1]/1%09%09!");
+        assert_eq!(plus.request(), "\t\t!");
+
+        let search = GopherUrl::from_str("gopher://gopher.example.net/7/search").unwrap();
+        match search.with_search("one\ttwo") {
+            Err(GopherError::InvalidQuery(ref query)) if query == "one\ttwo" => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn gopher_plus_round_trip() {
+        let input = "iWelcome\tfake\t(NULL)\t0
+1Gopher+ Directory\t/plus\tgopher.example.net\t70\t+
+7Gopher+ Form\t/ask\tgopher.example.net\t70\t?\tvendor-extension
+0Plain File\t/plain.txt\tgopher.example.net\t70
+.";
+        let directory: Directory = input.parse().expect("failed to parse sample directory");
+        let items = directory.items();
+        assert!(!items[0].is_gopher_plus());
+        assert!(items[1].is_gopher_plus());
+        assert_eq!(items[1].extra_fields(), &["+"]);
+        assert!(items[2].is_gopher_plus());
+        assert_eq!(items[2].extra_fields(), &["?", "vendor-extension"]);
+        assert!(!items[3].is_gopher_plus());
+        assert!(items[3].extra_fields().is_empty());
+
+        assert_eq!(format!("{}", directory), input);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let input = "0About internet Gopher\tStuff:About us\trawBits.micro.umn.edu\t70
+1Around University of Minnesota\tZ,5692,AUM\tunderdog.micro.umn.edu\tseventy
+.";
+        match Directory::from_str(input) {
+            Err(GopherError::BadPort { position, port }) => {
+                assert_eq!(position, Position::new(2, 67));
+                assert_eq!(port, "seventy");
+            },
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        match DirectoryItem::from_str("1Missing port\t/\tgopher.example.net") {
+            Err(GopherError::MissingField { position, field }) => {
+                assert_eq!(position, Position::new(1, 35));
+                assert_eq!(field, "port");
+            },
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let err = DirectoryItem::from_str("\x07Bell\t/\tgopher.example.net\t70").unwrap_err();
+        assert_eq!(err.to_string(), "invalid item type '\\u{7}' at line 1, column 1");
+        assert_eq!(err.at_line(12).position(), Some(Position::new(12, 1)));
+    }
+
+    #[test]
+    fn item_types() {
+        for c in "0123456789+TgIihsdp;cMPrXz".chars() {
+            assert_eq!(Type::from_char(c).as_char(), c);
+        }
+        assert_eq!(Type::from_char('z'), Type::Unknown('z'));
+
+        assert!(Type::File.is_file());
+        assert!(Type::PNG.is_file());
+        assert!(Type::BinArchive.is_file());
+        assert!(!Type::Directory.is_file());
+        assert!(!Type::SearchServer.is_file());
+        assert!(!Type::Info.is_file());
+
+        assert_eq!(Type::Html.category(), TypeCategory::Text);
+        assert_eq!(Type::SearchServer.category(), TypeCategory::Interactive);
+        assert_eq!(Type::Video.category(), TypeCategory::Media);
+        assert_eq!(Type::Error.category(), TypeCategory::Informational);
+        assert_eq!(Type::Unknown('z').category(), TypeCategory::Binary);
+
+        assert_eq!(Type::PDF.mime_type(), Some("application/pdf"));
+        assert_eq!(Type::Sound.mime_type(), Some("audio/*"));
+        assert_eq!(Type::TelnetSession.mime_type(), None);
+    }
+
+    #[test]
+    fn external_urls() {
+        let item = DirectoryItem::from_external_url("The Web", "https://example.net/", "gopher.example.net", 70);
+        assert_eq!(format!("{}", item), "hThe Web\tURL:https://example.net/\tgopher.example.net\t70");
+        assert!(item.is_external_url());
+
+        let item: DirectoryItem = "hOld style\t/URL:http://example.net/\tgopher.example.net\t70".parse().unwrap();
+        assert_eq!(item.external_url(), Some("http://example.net/"));
+
+        let item: DirectoryItem = "0Notes\t/notes/URL:list.txt\tgopher.example.net\t70".parse().unwrap();
+        assert!(!item.is_external_url());
+    }
 }
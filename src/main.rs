@@ -2,17 +2,49 @@ extern crate rustbox;
 extern crate gopher;
 
 use std::env;
-use std::io;
-use std::io::prelude::*;
-use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
 
 use gopher::*;
+use gopher::gopherplus::AskKind;
+use gopher::net::{CancellationToken, Client, Progress, Resource};
 
 use rustbox::{ Color, Key, RustBox };
 
 pub const MENU_KEYS: &'static str = "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ!@#$%^&*()-+_=";
 
+/// A line of a directory on screen: info text, or a link with its menu key
+/// number
+enum Row<'a> {
+    Text(&'a DirectoryItem),
+    Link(usize, &'a DirectoryItem),
+}
+
+/// The rows of a directory from the scroll position down, with the visible
+/// links numbered from 0
+fn visible_rows(dir: &Directory, scroll: usize) -> Vec<Row<'_>> {
+    let mut rows = Vec::new();
+    let mut line = 0;
+    let mut link_number = 0;
+    for section in dir.sections() {
+        for item in section.heading {
+            if line >= scroll {
+                rows.push(Row::Text(item));
+            }
+            line += 1;
+        }
+        for item in section.links {
+            if line >= scroll {
+                rows.push(Row::Link(link_number, item));
+                link_number += 1;
+            }
+            line += 1;
+        }
+    }
+    rows
+}
+
 enum State {
     DisplayDirectory( String, Directory, usize ),
     DisplayResource( String, String, usize ),
@@ -21,6 +53,7 @@ enum State {
 }
 
 struct Gopher {
+    client: Client,
     current_host: String,
     current_port: u16,
     current_selector: String,
@@ -28,59 +61,56 @@ struct Gopher {
 }
 
 impl Gopher {
-    pub fn new(host: &str, port: u16, selector: &str) -> Gopher {
-        let resource = get_resource(host, port, selector);
-        Gopher {
-            current_host: host.into(),
-            current_port: port,
-            current_selector: selector.into(),
-            states: vec![
-                match resource {
-                    Ok(s) => match Directory::from_str(&s) {
-                        Ok(directory) => State::DisplayDirectory(format!("{}:{} {}", host, port, selector), directory, 0),
-                        Err(e) => State::Error(e) },
-                    Err(e) => State::Error(GopherError::Io(e))
-                }
-            ],
-        }
+    /// Load the resource a URL points to, as whatever type the URL gives,
+    /// which can be cancelled with Esc like any other transfer
+    pub fn new(rb: &RustBox, client: Client, url: &GopherUrl) -> Gopher {
+        let mut gopher = Gopher {
+            client,
+            current_host: url.host.clone(),
+            current_port: url.port,
+            current_selector: url.selector.clone(),
+            states: Vec::new(),
+        };
+
+        let state = gopher.fetch(rb, &url.to_item(""), url.search.clone());
+        gopher.states.push(state);
+        gopher
     }
 
     fn display_directory(rb: &RustBox, dir: &Directory, scroll: usize) {
-        let mut line_number = 0;
-        let mut item_number = 0;
-        for item in dir.items().iter().skip(scroll) {
-            if item.is_info() {
-                rb.print(0, line_number, rustbox::RB_NORMAL, Color::White, Color::Black, &item.name);
-            } else {
-                let mut col = 0;
-                let button = format!("[{}]", &MENU_KEYS[item_number..item_number+1]);
-                rb.print(col, line_number, rustbox::RB_BOLD, Color::White, Color::Black, &button);
-
-                col += button.len()+1;
-
-                match item.t {
-                    Type::Unknown(c) => rb.print(col, line_number,
-                                                 rustbox::RB_BOLD, Color::White, Color::Red, &format!("{}",c)),
-                    Type::Directory => rb.print(col, line_number,
-                                                rustbox::RB_BOLD, Color::White, Color::Blue, "/"),
-                    _ => {}
+        for (line_number, row) in visible_rows(dir, scroll).into_iter().enumerate() {
+            match row {
+                Row::Text(item) => {
+                    rb.print(0, line_number, rustbox::RB_NORMAL, Color::White, Color::Black, &item.name);
+                },
+                Row::Link(item_number, item) => {
+                    let mut col = 0;
+                    let button = format!("[{}]", &MENU_KEYS[item_number..item_number+1]);
+                    rb.print(col, line_number, rustbox::RB_BOLD, Color::White, Color::Black, &button);
+
+                    col += button.len()+1;
+
+                    match item.t {
+                        Type::Unknown(c) => rb.print(col, line_number,
+                                                     rustbox::RB_BOLD, Color::White, Color::Red, &format!("{}",c)),
+                        Type::Directory => rb.print(col, line_number,
+                                                    rustbox::RB_BOLD, Color::White, Color::Blue, "/"),
+                        _ => {}
                     
-                }
+                    }
 
-                col += 2;
+                    col += 2;
                 
-                let name = format!("{}", item.name);
-                rb.print(col, line_number, rustbox::RB_UNDERLINE, Color::White, Color::Black, &name);
-                col += name.len()+1;
-
-                let link = format!("{host}:{port} {selector}",
-                                   selector=item.selector,
-                                   host=item.host,
-                                   port=item.port);
-                
-                item_number += 1;
+                    let name = format!("{}", item.name);
+                    rb.print(col, line_number, rustbox::RB_UNDERLINE, Color::White, Color::Black, &name);
+                    col += name.len()+1;
+
+                    let link = format!("{host}:{port} {selector}",
+                                       selector=item.selector,
+                                       host=item.host,
+                                       port=item.port);
+                },
             }
-            line_number += 1;
         }
 
         if scroll != 0 {
@@ -120,7 +150,7 @@ impl Gopher {
                 Gopher::display_status(rb, location);
             },
             State::ShowMessage(ref s) => Gopher::display_string(rb, s, 0),
-            State::Error(ref e) => Gopher::display_string(rb, &format!("{:?}",e), 0)
+            State::Error(ref e) => Gopher::display_string(rb, &format!("{}",e), 0)
         }
     }
 
@@ -165,26 +195,23 @@ impl Gopher {
 
     /// Choose the nth item in the current directory
     /// Shows an error if not already in a directory
-    pub fn activate_item(&mut self, n: usize) {
+    pub fn activate_item(&mut self, rb: &RustBox, n: usize) {
         let new_state = match *self.current_state() {
             State::DisplayDirectory(_, ref dir, scroll) => {
-                if let Some(item) = dir.items()
-                    .iter()
-                    .skip(scroll)
-                    .filter(|&item| !item.is_info())
-                    .nth(n) {
-                        match get_resource(&*item.host, item.port, &*item.selector) {
-                            Ok(resource) => match Directory::from_str(&resource) {
-                                Ok(directory) => State::DisplayDirectory(
-                                    format!("{}:{} {}", &*item.host, item.port, &*item.selector),
-                                    directory, 0
-                                ),
-                                Err(e) => State::DisplayResource(
-                                    format!("{}:{} {}", &*item.host, item.port, &*item.selector),
-                                    resource, 0
-                                )
-                            },
-                            Err(e) => State::Error(GopherError::Io(e))
+                let link = visible_rows(dir, scroll).into_iter().find_map(|row| match row {
+                    Row::Link(number, item) if number == n => Some(item),
+                    _ => None,
+                });
+                if let Some(item) = link {
+                        if item.has_ask_form() {
+                            self.fill_form(rb, item)
+                        } else if item.t == Type::SearchServer {
+                            match prompt(rb, &item.name, "", false) {
+                                Some(query) => self.fetch(rb, item, Some(query)),
+                                None => State::ShowMessage("Cancelled".into()),
+                            }
+                        } else {
+                            self.fetch(rb, item, None)
                         }
                     } else {
                         State::ShowMessage("No such item".into())
@@ -194,29 +221,135 @@ impl Gopher {
         };
         self.states.push(new_state);
     }
-}
 
-fn get_resource(host: &str, port: u16, selector: &str) -> Result<String, io::Error> {
-    let address = (host, port);
-    let mut stream = TcpStream::connect(address)?;
+    /// Fetch an item, or send a search query to it
+    fn fetch(&self, rb: &RustBox, item: &DirectoryItem, query: Option<String>) -> State {
+        let location = format!("{}:{} {}", &*item.host, item.port, &*item.selector);
+        let target = item.clone();
+        let result = self.run(rb, &location, move |client| match query {
+            Some(query) => client.search(&target, &query).map(Resource::SearchResults),
+            None => client.fetch(&target),
+        });
+
+        match result {
+            Ok(Resource::Menu(directory)) |
+            Ok(Resource::SearchResults(directory)) => State::DisplayDirectory(location, directory, 0),
+            Ok(Resource::Text(text)) => State::DisplayResource(location, text.to_string(), 0),
+            Ok(Resource::Binary(bytes)) => State::ShowMessage(format!("Binary file, {} bytes", bytes.len())),
+            Ok(Resource::Error(message)) => State::ShowMessage(format!("Server error: {}", message)),
+            Ok(Resource::ExternalUrl(url)) => State::ShowMessage(format!("External link: {}", url)),
+            Err(GopherError::Cancelled) => State::ShowMessage("Cancelled".into()),
+            Err(e) => State::Error(e)
+        }
+    }
 
-    // set default timeouts to 5 seconds
-    stream.set_read_timeout(Some(Duration::new(5, 0)))?;
-    stream.set_write_timeout(Some(Duration::new(5, 0)))?;
+    /// Run a request on a background thread, showing progress on the status
+    /// line until it finishes
+    /// Pressing Esc cancels the transfer
+    fn run<T, F>(&self, rb: &RustBox, location: &str, request: F) -> Result<T, GopherError>
+        where T: Send + 'static,
+              F: FnOnce(Client) -> Result<T, GopherError> + Send + 'static
+    {
+        let token = CancellationToken::new();
+        let latest: Arc<Mutex<Option<Progress>>> = Arc::new(Mutex::new(None));
+
+        let reported = latest.clone();
+        let client = self.client.to_builder()
+            .cancellation(token.clone())
+            .progress(move |progress| *reported.lock().unwrap() = Some(progress))
+            .build();
+        let transfer = thread::spawn(move || request(client));
+
+        while !transfer.is_finished() {
+            let status = match *latest.lock().unwrap() {
+                Some(progress) if progress.stalled => format!("Loading {}... {} bytes (stalled)", location, progress.bytes),
+                Some(progress) => format!("Loading {}... {} bytes", location, progress.bytes),
+                None => format!("Connecting to {}...", location),
+            };
+            Gopher::display_status(rb, &format!("{:width$}", status, width = rb.width()));
+            rb.present();
+
+            match rb.peek_event(Duration::from_millis(100), false) {
+                Ok(rustbox::Event::KeyEvent(Key::Esc)) => token.cancel(),
+                Err(e) => panic!("{:?}", e),
+                _ => { }
+            }
+        }
 
-    // send the directory selector
-    write!(stream, "{}\n", selector)?;
+        transfer.join().expect("transfer thread panicked")
+    }
 
-    let mut buffer = String::new();
-    stream.read_to_string(&mut buffer)?;
+    /// Fetch the Gopher+ ASK form for an item, prompt for each answer and
+    /// submit the form
+    fn fill_form(&self, rb: &RustBox, item: &DirectoryItem) -> State {
+        let location = format!("{}:{} {}", &*item.host, item.port, &*item.selector);
+        let target = item.clone();
+        let mut form = match self.run(rb, &location, move |client| client.read_item_form(&target)) {
+            Ok(form) => form,
+            Err(GopherError::Cancelled) => return State::ShowMessage("Cancelled".into()),
+            Err(e) => return State::Error(e),
+        };
 
-    Ok(buffer)
-}
+        for field in form.questions_mut() {
+            let hint = match field.kind {
+                AskKind::Choose => format!(" ({})", field.options.join("/")),
+                AskKind::Select => String::from(" (1/0)"),
+                _ => String::new(),
+            };
+            let question = format!("{}{}", field.prompt, hint);
+            let hidden = field.kind == AskKind::AskPassword;
+
+            // keep asking until we get an acceptable answer
+            loop {
+                let answer = match prompt(rb, &question, &field.answer, hidden) {
+                    Some(answer) => answer,
+                    None => return State::ShowMessage("Cancelled".into()),
+                };
+                if field.set_answer(&answer).is_ok() {
+                    break;
+                }
+            }
+        }
 
-fn get_resource_from_item(item: &DirectoryItem) -> Result<String, io::Error> {
-    get_resource(&item.host, item.port, &item.selector)
+        let target = item.clone();
+        match self.run(rb, &location, move |client| client.submit_item_form(&target, &form)) {
+            Ok(response) => match Directory::from_str(&response) {
+                Ok(directory) => State::DisplayDirectory(location, directory, 0),
+                Err(_) => State::DisplayResource(location, response, 0),
+            },
+            Err(GopherError::Cancelled) => State::ShowMessage("Cancelled".into()),
+            Err(e) => State::Error(e),
+        }
+    }
 }
 
+/// Read a line of input on the status line
+/// Returns None if the user pressed Esc
+fn prompt(rb: &RustBox, question: &str, default: &str, hidden: bool) -> Option<String> {
+    let mut input = String::from(default);
+    loop {
+        let shown = if hidden { "*".repeat(input.chars().count()) } else { input.clone() };
+        let line = format!("{}: {}", question, shown);
+        rb.print(0, rb.height()-1,
+                 rustbox::RB_NORMAL, Color::White, Color::Blue,
+                 &format!("{:width$}", line, width = rb.width()));
+        rb.present();
+
+        match rb.poll_event(false) {
+            Ok(rustbox::Event::KeyEvent(key)) => {
+                match key {
+                    Key::Enter => { return Some(input); }
+                    Key::Esc => { return None; }
+                    Key::Backspace => { input.pop(); }
+                    Key::Char(c) => { input.push(c); }
+                    _ => { }
+                }
+            },
+            Err(e) => panic!("{:?}", e),
+            _ => { }
+        }
+    }
+}
 
 fn main() {
     let rustbox = match RustBox::init(rustbox::InitOptions {
@@ -228,13 +361,25 @@ fn main() {
         Result::Err(e) => panic!("rustbox err: {}", e),
     };
     
-    let mut args = env::args();
-
-    let host = args.nth(1).unwrap_or(String::from("gopher.quux.org"));
-    let port = 70;
-    let selector = args.next().unwrap_or(String::from(""));
+    let mut args = env::args().skip(1);
+
+    // accept either a full gopher:// url, or a hostname followed by an
+    // optional directory selector
+    let target = args.next().unwrap_or(String::from("gopher.quux.org"));
+    let url = match GopherUrl::from_str(&target) {
+        Ok(url) => url,
+        Err(_) => {
+            let mut url = GopherUrl::from_str(&format!("gopher://{}", target))
+                .expect("could not parse url");
+            if let Some(selector) = args.next() {
+                url.t = Type::Directory;
+                url.selector = selector;
+            }
+            url
+        },
+    };
 
-    let mut gopher = Gopher::new(&host, port, &selector);
+    let mut gopher = Gopher::new(&rustbox, Client::new(), &url);
 
     rustbox.clear();
 
@@ -256,7 +401,7 @@ fn main() {
                     Key::Char(pressed) => {
                         for (n, c) in MENU_KEYS.chars().enumerate() {
                             if pressed == c {
-                                gopher.activate_item(n)
+                                gopher.activate_item(&rustbox, n)
                             }
                         }
                     }
@@ -1,48 +1,1032 @@
 //! Network Utilities
 //!
-//! This module defines a handful of helper functions for talking to remote
-//! Gopher servers.  These can be useful for proof-of-concept or getting for
-//! getting started, but probably shouldn't be used for anything more serious.
+//! A `Client` fetches resources from remote Gopher servers, with configurable
+//! timeouts, size limits and retries.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use gopher::net::Client;
+//!
+//! let client = Client::builder()
+//!     .connect_timeout(Duration::from_secs(10))
+//!     .total_timeout(Duration::from_secs(60))
+//!     .max_bytes(1024 * 1024)
+//!     .retries(2)
+//!     .build();
+//!
+//! let directory = client.read_directory("gopher.floodgap.com", 70, "/").unwrap();
+//! ```
+//!
+//! The free functions in this module use a `Client` with the default
+//! settings.  These can be useful for proof-of-concept or getting started,
+//! but probably shouldn't be used for anything more serious.
 
+use std::fmt;
 use std::io;
+use std::io::BufReader;
 use std::io::prelude::*;
-use std::net::{TcpStream, ToSocketAddrs};
-use std::time::Duration;
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type as SocketType};
 
 use GopherError;
+use Type;
+use TypeCategory;
+use DirectoryItem;
 use Directory;
+use DirectoryReader;
+use RawDirectory;
+use Charset;
+use TextDocument;
+use external_url;
 
-/// Utility function to read a resource from a server
-fn read_string<T: ToSocketAddrs>(address: T, selector: &str) -> Result<String, io::Error> {
-    let mut stream = TcpStream::connect(address)?;
+/// A resource fetched from a server, depending on the type of item
+#[derive(Clone, Debug)]
+pub enum Resource {
+    Menu(Directory),
+    Text(TextDocument),
+    Binary(Vec<u8>),
+    /// The results of a search, which are a menu
+    SearchResults(Directory),
+    /// An error item, or an error reported by the server
+    Error(String),
+    /// A link outside of gopherspace, which wasn't fetched, including telnet
+    /// sessions
+    ExternalUrl(String),
+}
+
+/// The progress of a transfer, as passed to a progress callback
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Progress {
+    /// The number of bytes received so far
+    pub bytes: u64,
+    /// The time since the request was sent
+    pub elapsed: Duration,
+    /// Whether the server has sent nothing for the last `STALL_INTERVAL`
+    pub stalled: bool,
+}
+
+/// How often the progress callback is called while the server is sending
+/// nothing, and the cancellation token checked
+pub const STALL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A flag for cancelling transfers, which can be shared with another thread
+///
+/// Cancelled transfers fail with `GopherError::Cancelled`.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancel every transfer using this token
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// A function called with the progress of each transfer
+pub type ProgressCallback = dyn Fn(Progress) + Send + Sync;
+
+/// The time by which a whole request must finish, from the client's total
+/// timeout
+#[derive(Clone, Copy, Debug)]
+struct Deadline(Option<Instant>);
+
+impl Deadline {
+    /// The time left before the deadline, failing if it has passed
+    fn remaining(&self) -> Result<Option<Duration>, GopherError> {
+        match self.0 {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if remaining > Duration::from_millis(0) => Ok(Some(remaining)),
+                _ => Err(GopherError::Timeout(io::Error::new(io::ErrorKind::TimedOut, "total timeout exceeded"))),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+/// The shorter of two optional timeouts
+fn shortest(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// A function to look up the addresses of a host and port, in place of the
+/// system resolver
+pub type Resolver = dyn Fn(&str, u16) -> io::Result<Vec<SocketAddr>> + Send + Sync;
+
+/// A configurable Gopher client
+///
+/// Clients are cheap to clone, and can be shared between threads.
+#[derive(Clone)]
+pub struct Client {
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    total_timeout: Option<Duration>,
+    max_bytes: Option<u64>,
+    retries: u32,
+    retry_delay: Duration,
+    nodelay: bool,
+    ttl: Option<u32>,
+    local_address: Option<IpAddr>,
+    resolver: Option<Arc<Resolver>>,
+    progress: Option<Arc<ProgressCallback>>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl Default for Client {
+    /// A client with 5 second connect and read timeouts, and no other limits
+    fn default() -> Client {
+        Client {
+            connect_timeout: Some(Duration::from_secs(5)),
+            read_timeout: Some(Duration::from_secs(5)),
+            total_timeout: None,
+            max_bytes: None,
+            retries: 0,
+            retry_delay: Duration::from_millis(500),
+            nodelay: false,
+            ttl: None,
+            local_address: None,
+            resolver: None,
+            progress: None,
+            cancellation: None,
+        }
+    }
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("connect_timeout", &self.connect_timeout)
+            .field("read_timeout", &self.read_timeout)
+            .field("total_timeout", &self.total_timeout)
+            .field("max_bytes", &self.max_bytes)
+            .field("retries", &self.retries)
+            .field("retry_delay", &self.retry_delay)
+            .field("nodelay", &self.nodelay)
+            .field("ttl", &self.ttl)
+            .field("local_address", &self.local_address)
+            .field("resolver", &self.resolver.is_some())
+            .field("progress", &self.progress.is_some())
+            .field("cancellation", &self.cancellation)
+            .finish()
+    }
+}
+
+/// A builder for Clients, starting from the default settings
+#[derive(Clone, Debug, Default)]
+pub struct ClientBuilder {
+    client: Client,
+}
+
+impl ClientBuilder {
+    /// Set the time allowed to connect to each address of a server
+    pub fn connect_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.client.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the time allowed for each read or write, after which a silent
+    /// server is given up on
+    pub fn read_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.client.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the time allowed for a whole request, from looking up the server
+    /// to reading the last byte, including any retries
+    ///
+    /// Looking up the server's address can't be interrupted, so a slow
+    /// lookup may run past the timeout; the request then fails as soon as
+    /// the lookup returns.
+    pub fn total_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.client.total_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the largest response accepted, in bytes
+    pub fn max_bytes(mut self, max_bytes: u64) -> ClientBuilder {
+        self.client.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Set how many times to retry a request which failed to connect or
+    /// timed out
+    ///
+    /// Note that a request which timed out part way through is sent again in
+    /// full, which may not be wanted for form submissions.
+    pub fn retries(mut self, retries: u32) -> ClientBuilder {
+        self.client.retries = retries;
+        self
+    }
+
+    /// Set the time to wait between retries
+    pub fn retry_delay(mut self, delay: Duration) -> ClientBuilder {
+        self.client.retry_delay = delay;
+        self
+    }
+
+    /// Set `TCP_NODELAY` on connections
+    pub fn nodelay(mut self, nodelay: bool) -> ClientBuilder {
+        self.client.nodelay = nodelay;
+        self
+    }
+
+    /// Set the IP time-to-live of connections
+    pub fn ttl(mut self, ttl: u32) -> ClientBuilder {
+        self.client.ttl = Some(ttl);
+        self
+    }
+
+    /// Connect from the given local address, on a port chosen by the system
+    ///
+    /// Only server addresses of the same family, IPv4 or IPv6, are tried.
+    pub fn local_address(mut self, address: IpAddr) -> ClientBuilder {
+        self.client.local_address = Some(address);
+        self
+    }
+
+    /// Look up servers with the given function instead of the system
+    /// resolver
+    pub fn resolver<F>(mut self, resolver: F) -> ClientBuilder
+        where F: Fn(&str, u16) -> io::Result<Vec<SocketAddr>> + Send + Sync + 'static
+    {
+        self.client.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Call a function with the progress of each transfer, after each block
+    /// of data arrives, and regularly while the server is stalled
+    pub fn progress<F>(mut self, callback: F) -> ClientBuilder
+        where F: Fn(Progress) + Send + Sync + 'static
+    {
+        self.client.progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Check a cancellation token before each connection attempt, while
+    /// waiting to retry, and between reads
+    ///
+    /// A connection attempt which has started runs until it succeeds or its
+    /// connect timeout passes.
+    pub fn cancellation(mut self, token: CancellationToken) -> ClientBuilder {
+        self.client.cancellation = Some(token);
+        self
+    }
+
+    pub fn build(self) -> Client {
+        self.client
+    }
+}
+
+impl Client {
+    /// Create a client with the default settings
+    pub fn new() -> Client {
+        Client::default()
+    }
+
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// Start a builder from this client's settings, for example to add a
+    /// progress callback for one transfer
+    pub fn to_builder(&self) -> ClientBuilder {
+        ClientBuilder { client: self.clone() }
+    }
+
+    /// Fail if the transfer has been cancelled
+    fn check_cancelled(&self) -> Result<(), GopherError> {
+        match self.cancellation {
+            Some(ref token) if token.is_cancelled() => Err(GopherError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    fn report(&self, progress: Progress) {
+        if let Some(ref callback) = self.progress {
+            callback(progress);
+        }
+    }
+
+    /// Start the deadline for a request
+    fn deadline(&self) -> Deadline {
+        Deadline(self.total_timeout.map(|timeout| Instant::now() + timeout))
+    }
+
+    /// Look up the addresses of a server, failing if the deadline passed
+    /// while waiting for the lookup
+    fn resolve(&self, host: &str, port: u16, deadline: Deadline) -> Result<Vec<SocketAddr>, GopherError> {
+        let addrs = match self.resolver {
+            Some(ref resolver) => resolver(host, port)?,
+            None => (host, port).to_socket_addrs()?.collect(),
+        };
+        if addrs.is_empty() {
+            let e = io::Error::new(io::ErrorKind::NotFound, format!("no addresses found for {}", host));
+            return Err(GopherError::from(e));
+        }
+        deadline.remaining()?;
+        Ok(addrs)
+    }
+
+    /// Connect to the first address which accepts the connection
+    fn connect(&self, addrs: &[SocketAddr], deadline: Deadline) -> Result<TcpStream, GopherError> {
+        let mut last_error = None;
+        let family_matches = |addr: &&SocketAddr| self.local_address.is_none_or(|local| local.is_ipv4() == addr.is_ipv4());
+        for addr in addrs.iter().filter(family_matches) {
+            self.check_cancelled()?;
+            let timeout = shortest(self.connect_timeout, deadline.remaining()?);
+            match self.open_socket(addr, timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(self.read_timeout)?;
+                    stream.set_write_timeout(shortest(self.read_timeout, deadline.remaining()?))?;
+                    stream.set_nodelay(self.nodelay)?;
+                    if let Some(ttl) = self.ttl {
+                        stream.set_ttl(ttl)?;
+                    }
+                    return Ok(stream);
+                },
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(GopherError::from(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses"))))
+    }
+
+    /// Open a connection to one address, from the local address if one is set
+    fn open_socket(&self, addr: &SocketAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+        let local = match self.local_address {
+            Some(local) => local,
+            None => return match timeout {
+                Some(timeout) => TcpStream::connect_timeout(addr, timeout),
+                None => TcpStream::connect(addr),
+            },
+        };
+
+        let socket = Socket::new(Domain::for_address(*addr), SocketType::STREAM, Some(Protocol::TCP))?;
+        socket.bind(&SocketAddr::new(local, 0).into())?;
+        match timeout {
+            Some(timeout) => socket.connect_timeout(&(*addr).into(), timeout)?,
+            None => socket.connect(&(*addr).into())?,
+        }
+        Ok(socket.into())
+    }
+
+    /// Connect to a server and send a request, returning the response to be
+    /// read
+    ///
+    /// `URL:` selectors point outside of gopherspace, and are never sent.
+    fn send_request(&self, addrs: &[SocketAddr], selector: &[u8], data: &[u8], deadline: Deadline) -> Result<ResponseReader, GopherError> {
+        if let Some(url) = external_url(&String::from_utf8_lossy(selector)) {
+            return Err(GopherError::ExternalUrl(url.into()));
+        }
+
+        self.check_cancelled()?;
+        let mut stream = self.connect(addrs, deadline)?;
+        stream.write_all(selector)?;
+        stream.write_all(b"\n")?;
+        stream.write_all(data)?;
+
+        Ok(ResponseReader::new(stream, self.clone(), deadline))
+    }
+
+    /// Run a request, retrying as configured if it fails to connect or
+    /// times out, unless the deadline would pass while waiting to retry
+    fn with_retries<T, F>(&self, deadline: Deadline, mut request: F) -> Result<T, GopherError>
+        where F: FnMut() -> Result<T, GopherError>
+    {
+        let mut attempt = 0;
+        loop {
+            let result = request();
+            match result {
+                Err(GopherError::Timeout(_)) |
+                Err(GopherError::ConnectionRefused(_)) if attempt < self.retries => {},
+                _ => return result,
+            }
+            match deadline.remaining() {
+                Ok(Some(remaining)) if remaining <= self.retry_delay => return result,
+                Err(_) => return result,
+                _ => {},
+            }
+            attempt += 1;
+
+            // wait in short steps, so a cancellation isn't held up
+            let resume = Instant::now() + self.retry_delay;
+            loop {
+                self.check_cancelled()?;
+                let now = Instant::now();
+                if now >= resume {
+                    break;
+                }
+                thread::sleep((resume - now).min(STALL_INTERVAL));
+            }
+        }
+    }
+
+    /// Connect and send a request, retrying as configured, and return the
+    /// response to be read
+    fn open(&self, host: &str, port: u16, selector: &[u8], deadline: Deadline) -> Result<ResponseReader, GopherError> {
+        let addrs = self.resolve(host, port, deadline)?;
+        self.with_retries(deadline, || self.send_request(&addrs, selector, b"", deadline))
+    }
+
+    /// Send a request to the given addresses and read the whole response,
+    /// retrying as configured
+    fn request_addrs(&self, addrs: &[SocketAddr], selector: &[u8], data: &[u8], deadline: Deadline) -> Result<Vec<u8>, GopherError> {
+        self.with_retries(deadline, || {
+            let mut response = self.send_request(addrs, selector, data, deadline)?;
+            let mut buffer = Vec::new();
+            response.copy_to(&mut buffer)?;
+            Ok(buffer)
+        })
+    }
+
+    /// Send a request and read the whole response, retrying as configured
+    pub(crate) fn request(&self, host: &str, port: u16, selector: &[u8], data: &[u8]) -> Result<Vec<u8>, GopherError> {
+        let deadline = self.deadline();
+        let addrs = self.resolve(host, port, deadline)?;
+        self.request_addrs(&addrs, selector, data, deadline)
+    }
+
+    /// Read the specified resource without decoding it
+    pub fn read_bytes(&self, host: &str, port: u16, selector: &[u8]) -> Result<Vec<u8>, GopherError> {
+        self.request(host, port, selector, b"")
+    }
+
+    /// Download the resource an item links to, copying the raw bytes to `out`
+    /// as they arrive
+    ///
+    /// Returns the number of bytes downloaded.  Only connecting is retried,
+    /// and if the response is larger than the client's `max_bytes` the bytes
+    /// up to the limit will already have been written.
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use gopher::*;
+    /// use gopher::net::Client;
+    ///
+    /// let item = DirectoryItem::from_str("9Archive\t/archive.zip\tgopher.example.net\t70").unwrap();
+    /// let mut file = File::create("archive.zip").unwrap();
+    /// let bytes = Client::new().download(&item, &mut file).unwrap();
+    /// println!("downloaded {} bytes", bytes);
+    /// ```
+    pub fn download<W: Write + ?Sized>(&self, item: &DirectoryItem, out: &mut W) -> Result<u64, GopherError> {
+        let mut response = self.open(&item.host, item.port, item.selector.as_bytes(), self.deadline())?;
+        response.copy_to(out)
+    }
+
+    /// Read the specified directory, parsing it as it arrives
+    pub fn read_directory(&self, host: &str, port: u16, selector: &str) -> Result<Directory, GopherError> {
+        Directory::from_reader(BufReader::new(self.open(host, port, selector.as_bytes(), self.deadline())?))
+    }
+
+    /// Read the specified directory one item at a time, as the lines arrive
+    ///
+    /// The client's limits apply while the items are read, and are reported
+    /// as errors from the reader.
+    pub fn stream_directory(&self, host: &str, port: u16, selector: &str) -> Result<DirectoryReader<BufReader<ResponseReader>>, GopherError> {
+        let response = self.open(host, port, selector.as_bytes(), self.deadline())?;
+        Ok(DirectoryReader::new(BufReader::new(response)))
+    }
+
+    /// Fetch the resource an item links to
+    ///
+    /// The kind of resource is chosen from the item type.  Items of unknown
+    /// type are read as a menu if they parse as one, then as text if they're
+    /// valid UTF-8, or otherwise as binary data.
+    ///
+    /// Menus are decoded in the character set their names and selectors
+    /// appear to use, and parsed leniently if they're malformed.
+    pub fn fetch(&self, item: &DirectoryItem) -> Result<Resource, GopherError> {
+        if let Some(url) = item.external_url() {
+            return Ok(Resource::ExternalUrl(url.into()));
+        }
+
+        match item.t {
+            Type::TelnetSession =>
+                return Ok(Resource::ExternalUrl(format!("telnet://{}:{}", item.host, item.port))),
+            Type::Tn3270Session =>
+                return Ok(Resource::ExternalUrl(format!("tn3270://{}:{}", item.host, item.port))),
+            Type::CSOPhoneBook =>
+                return Ok(Resource::Error(format!("CSO phone books aren't supported: {}", item.name))),
+            _ => {},
+        }
+
+        if item.t.category() == TypeCategory::Informational {
+            return Ok(Resource::Error(item.name.clone()));
+        }
+
+        let buffer = self.request(&item.host, item.port, item.selector.as_bytes(), b"")?;
+
+        Ok(match item.t.category() {
+            TypeCategory::Menu => menu_or_error(parse_menu(&buffer)).map_or_else(Resource::Error, Resource::Menu),
+            TypeCategory::Interactive =>
+                menu_or_error(parse_menu(&buffer)).map_or_else(Resource::Error, Resource::SearchResults),
+            TypeCategory::Text => {
+                let text = Charset::detect(&buffer).decode(&buffer);
+                Resource::Text(TextDocument::decode(&text))
+            },
+            _ if matches!(item.t, Type::Unknown(_)) => sniff(buffer),
+            _ => Resource::Binary(buffer),
+        })
+    }
+
+    /// Send a query to a search server, and read the results
+    ///
+    /// The query is sent after the item's selector, separated by a tab, so
+    /// it can't contain tabs or line breaks itself.  A response made up only
+    /// of error items is returned as `GopherError::Server`.
+    pub fn search(&self, item: &DirectoryItem, query: &str) -> Result<Directory, GopherError> {
+        if query.contains(&['\t', '\r', '\n'][..]) {
+            return Err(GopherError::InvalidQuery(query.into()));
+        }
+
+        let request = format!("{}\t{}", item.selector, query);
+        let buffer = self.request(&item.host, item.port, request.as_bytes(), b"")?;
+        menu_or_error(parse_menu(&buffer)).map_err(GopherError::Server)
+    }
+
+    /// Read the specified text file
+    pub fn read_document(&self, host: &str, port: u16, selector: &str) -> Result<TextDocument, GopherError> {
+        let buffer = utf8(self.request(host, port, selector.as_bytes(), b"")?)?;
+        Ok(TextDocument::decode(&buffer))
+    }
+
+    /// Read the specified directory, keeping the names and selectors as the
+    /// exact bytes sent by the server
+    pub fn read_raw_directory(&self, host: &str, port: u16, selector: &[u8]) -> Result<RawDirectory, GopherError> {
+        let buffer = self.request(host, port, selector, b"")?;
+        RawDirectory::from_bytes(&buffer)
+    }
+
+    /// Read the specified text resource, decoding it with the given character
+    /// set, or a guessed one if not specified
+    ///
+    /// Returns the decoded text, and the character set used.
+    pub fn read_text(&self, host: &str, port: u16, selector: &[u8], charset: Option<Charset>) -> Result<(String, Charset), GopherError> {
+        let buffer = self.request(host, port, selector, b"")?;
+        let charset = charset.unwrap_or_else(|| Charset::detect(&buffer));
+        Ok((charset.decode(&buffer), charset))
+    }
+}
+
+/// A response being read from a server, which enforces the client's
+/// timeouts and size limit, reports progress, and checks for cancellation
+///
+/// Reads wait for at most `STALL_INTERVAL` at a time, so that stalls can be
+/// reported and cancellation noticed while the server is silent.  Errors from
+/// the limits are returned as `io::Error`s wrapping the `GopherError`, which
+/// `GopherError::from` unwraps again.
+pub struct ResponseReader {
+    stream: TcpStream,
+    client: Client,
+    deadline: Deadline,
+    start: Instant,
+    last_data: Instant,
+    transferred: u64,
+}
+
+impl ResponseReader {
+    fn new(stream: TcpStream, client: Client, deadline: Deadline) -> ResponseReader {
+        let now = Instant::now();
+        ResponseReader { stream, client, deadline, start: now, last_data: now, transferred: 0 }
+    }
 
-    // set default timeouts to 5 seconds
-    stream.set_read_timeout(Some(Duration::new(5, 0)))?;
-    stream.set_write_timeout(Some(Duration::new(5, 0)))?;
+    /// The number of bytes read so far
+    pub fn transferred(&self) -> u64 {
+        self.transferred
+    }
+
+    /// Read the next block of data, returning 0 at the end of the response
+    fn read_block(&mut self, buf: &mut [u8]) -> Result<usize, GopherError> {
+        loop {
+            self.client.check_cancelled()?;
+            self.stream.set_read_timeout(shortest(Some(STALL_INTERVAL), self.deadline.remaining()?))?;
 
-    // send the directory selector
-    write!(stream, "{}\n", selector)?;
+            let n = match self.stream.read(buf) {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => match e.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+                        if self.client.read_timeout.is_some_and(|read| self.last_data.elapsed() >= read) {
+                            return Err(GopherError::from(e));
+                        }
+                        self.client.report(Progress { bytes: self.transferred, elapsed: self.start.elapsed(), stalled: true });
+                        continue;
+                    },
+                    _ => return Err(GopherError::from(e)),
+                },
+            };
+            if n == 0 {
+                return Ok(0);
+            }
+
+            if let Some(limit) = self.client.max_bytes {
+                if self.transferred + n as u64 > limit {
+                    return Err(GopherError::ResponseTooLarge { limit });
+                }
+            }
+            self.transferred += n as u64;
+            self.last_data = Instant::now();
+            self.client.report(Progress { bytes: self.transferred, elapsed: self.start.elapsed(), stalled: false });
+            return Ok(n);
+        }
+    }
+
+    /// Copy the rest of the response to `out`, returning the total number of
+    /// bytes read
+    fn copy_to<W: Write + ?Sized>(&mut self, out: &mut W) -> Result<u64, GopherError> {
+        let mut chunk = [0; 8192];
+        loop {
+            match self.read_block(&mut chunk)? {
+                0 => return Ok(self.transferred),
+                n => out.write_all(&chunk[..n])?,
+            }
+        }
+    }
+}
+
+impl Read for ResponseReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_block(buf).map_err(|e| match e {
+            GopherError::Io(e) | GopherError::Timeout(e) | GopherError::ConnectionRefused(e) => e,
+            e => io::Error::other(e),
+        })
+    }
+}
+
+impl fmt::Debug for ResponseReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ResponseReader")
+            .field("stream", &self.stream)
+            .field("transferred", &self.transferred)
+            .finish()
+    }
+}
+
+/// Check that a response is UTF-8
+pub(crate) fn utf8(buffer: Vec<u8>) -> Result<String, GopherError> {
+    String::from_utf8(buffer).map_err(|e| GopherError::non_utf8(e.as_bytes(), e.utf8_error()))
+}
+
+/// Parse a menu from a server, decoding it in the character set its names and
+/// selectors appear to use
+///
+/// A malformed menu is still more use than an error, so if it doesn't parse
+/// it's decoded as a whole and parsed leniently instead.
+fn parse_menu(buffer: &[u8]) -> Directory {
+    match RawDirectory::from_bytes(buffer) {
+        Ok(raw) => raw.decode(raw.detect_charset()),
+        Err(_) => Directory::from_str_lenient(&Charset::detect(buffer).decode(buffer)).0,
+    }
+}
+
+/// Treat a menu consisting only of error items as an error from the server,
+/// returning the error messages
+fn menu_or_error(directory: Directory) -> Result<Directory, String> {
+    let errors: Vec<&str> = directory.items().iter()
+        .filter(|item| item.t == Type::Error)
+        .map(|item| item.name.as_str())
+        .collect();
+    if !errors.is_empty() && directory.links().all(|item| item.t == Type::Error) {
+        Err(errors.join("\n"))
+    } else {
+        Ok(directory)
+    }
+}
+
+/// Guess what kind of resource a response of unknown type is
+fn sniff(buffer: Vec<u8>) -> Resource {
+    match String::from_utf8(buffer) {
+        Ok(text) => match Directory::from_str(&text) {
+            Ok(directory) if !directory.items().is_empty() => menu_or_error(directory).map_or_else(Resource::Error, Resource::Menu),
+            _ if !text.contains('\0') => Resource::Text(TextDocument::decode(&text)),
+            _ => Resource::Binary(text.into_bytes()),
+        },
+        Err(e) => Resource::Binary(e.into_bytes()),
+    }
+}
 
-    let mut buffer = String::new();
-    stream.read_to_string(&mut buffer)?;
+/// Send a request with a default Client, for the free functions which take
+/// any `ToSocketAddrs`
+pub(crate) fn request<T: ToSocketAddrs>(address: T, selector: &[u8], data: &[u8]) -> Result<Vec<u8>, GopherError> {
+    let client = Client::default();
+    let addrs: Vec<SocketAddr> = address.to_socket_addrs()?.collect();
+    client.request_addrs(&addrs, selector, data, client.deadline())
+}
 
-    Ok(buffer)
+/// Utility function to read a resource from a server
+fn read_string<T: ToSocketAddrs>(address: T, selector: &str) -> Result<String, GopherError> {
+    utf8(request(address, selector.as_bytes(), b"")?)
 }
 
 /// Connect to a Gopher server and read the specified directory
 pub fn read_directory<T: ToSocketAddrs>(address: T, selector: &str) -> Result<Directory, GopherError> {
-    let buffer = read_string(address, selector)?;
-    Directory::from_str(&buffer)
+    let buffer = request(address, selector.as_bytes(), b"")?;
+    Directory::from_reader(&buffer[..])
+}
+
+/// Connect to a Gopher server and read the specified directory one item at a
+/// time, as the lines arrive
+pub fn stream_directory<T: ToSocketAddrs>(address: T, selector: &str) -> Result<DirectoryReader<BufReader<ResponseReader>>, GopherError> {
+    let client = Client::default();
+    let addrs: Vec<SocketAddr> = address.to_socket_addrs()?.collect();
+    let response = client.send_request(&addrs, selector.as_bytes(), b"", client.deadline())?;
+    Ok(DirectoryReader::new(BufReader::new(response)))
 }
 
 /// Connect to a Gopher server and read the specified resource
 /// If the result can be parsed as a Directory, return the result, otherwise
-/// return the plain string
+/// return the text, decoded as a `TextDocument`
+#[deprecated(note = "guesses the type of resource; use `Client::fetch` instead")]
 pub fn read_directory_or_resource<T: ToSocketAddrs>(address: T, selector: &str) -> Result<Result<Directory, String>, GopherError> {
     let buffer = read_string(address, selector)?;
     if let Ok(directory) = Directory::from_str(&buffer) {
         Ok(Ok(directory))
     } else {
-        Ok(Err(buffer))
+        Ok(Err(TextDocument::decode(&buffer).to_string()))
+    }
+}
+
+/// Connect to a Gopher server and read the specified resource without
+/// decoding it, such as an image or archive
+pub fn read_bytes<T: ToSocketAddrs>(address: T, selector: &[u8]) -> Result<Vec<u8>, GopherError> {
+    request(address, selector, b"")
+}
+
+/// Connect to a Gopher server and read the specified text file
+pub fn read_document<T: ToSocketAddrs>(address: T, selector: &str) -> Result<TextDocument, GopherError> {
+    let buffer = read_string(address, selector)?;
+    Ok(TextDocument::decode(&buffer))
+}
+
+/// Connect to a Gopher server and read the specified directory, keeping the
+/// names and selectors as the exact bytes sent by the server
+///
+/// The selector is sent unchanged, so the selector of a `RawDirectoryItem`
+/// can be used to follow a link in a menu that isn't UTF-8.
+pub fn read_raw_directory<T: ToSocketAddrs>(address: T, selector: &[u8]) -> Result<RawDirectory, GopherError> {
+    let buffer = request(address, selector, b"")?;
+    RawDirectory::from_bytes(&buffer)
+}
+
+/// Connect to a Gopher server and read the specified text resource, decoding
+/// it with the given character set, or a guessed one if not specified
+///
+/// Returns the decoded text, and the character set used.
+pub fn read_text<T: ToSocketAddrs>(address: T, selector: &[u8], charset: Option<Charset>) -> Result<(String, Charset), GopherError> {
+    let buffer = request(address, selector, b"")?;
+    let charset = charset.unwrap_or_else(|| Charset::detect(&buffer));
+    Ok((charset.decode(&buffer), charset))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Start a server on a local port which answers one request per response
+    /// with the given bytes, returning the port
+    pub(crate) fn serve(responses: Vec<Vec<u8>>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request).unwrap();
+                // the client may hang up early, when testing limits
+                let _ = stream.write_all(&response);
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn client_limits() {
+        let menu = b"0About\t/about.txt\tlocalhost\t70\r\n.\r\n".to_vec();
+        let port = serve(vec![menu.clone(), menu.clone()]);
+
+        let client = Client::builder()
+            .resolver(|_, port| Ok(vec![SocketAddr::from(([127, 0, 0, 1], port))]))
+            .build();
+        let directory = client.read_directory("gopher.invalid", port, "/").unwrap();
+        assert_eq!(directory.items()[0].selector, "/about.txt");
+
+        let client = Client::builder().max_bytes(10).build();
+        match client.read_directory("127.0.0.1", port, "/") {
+            Err(GopherError::ResponseTooLarge { limit: 10 }) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        match Client::new().read_directory("127.0.0.1", port, "URL:https://example.net/") {
+            Err(GopherError::ExternalUrl(_)) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn total_timeout_and_local_address() {
+        // a port with nothing listening, so every attempt is refused
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let client = Client::builder()
+            .total_timeout(Duration::from_millis(300))
+            .retries(100)
+            .retry_delay(Duration::from_millis(50))
+            .build();
+        let start = Instant::now();
+        match client.read_bytes("127.0.0.1", port, b"/") {
+            Err(GopherError::ConnectionRefused(_)) | Err(GopherError::Timeout(_)) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let port = serve(vec![b"hello".to_vec()]);
+        let client = Client::builder().local_address(IpAddr::from([127, 0, 0, 1])).build();
+        assert_eq!(client.read_bytes("127.0.0.1", port, b"/").unwrap(), b"hello");
+    }
+
+    #[test]
+    fn stream_with_limits() {
+        let menu = b"0About\t/about.txt\tlocalhost\t70\r\n0Other\t/other.txt\tlocalhost\t70\r\n.\r\n".to_vec();
+        let port = serve(vec![menu.clone(), menu]);
+
+        let items: Vec<_> = Client::new().stream_directory("127.0.0.1", port, "/").unwrap().collect();
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().all(|item| item.is_ok()));
+
+        let client = Client::builder().max_bytes(40).build();
+        let mut reader = client.stream_directory("127.0.0.1", port, "/").unwrap();
+        match reader.next() {
+            Some(Err(GopherError::ResponseTooLarge { limit: 40 })) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn download_bytes() {
+        let data: Vec<u8> = (0..20000u32).map(|n| (n % 256) as u8).collect();
+        let port = serve(vec![data.clone(), data.clone(), data.clone()]);
+        let item = DirectoryItem { t: Type::BinArchive, name: "Archive".into(), selector: "/archive.zip".into(), host: "127.0.0.1".into(), port, extra: Vec::new() };
+
+        let mut out = Vec::new();
+        assert_eq!(Client::new().download(&item, &mut out).unwrap(), 20000);
+        assert_eq!(out, data);
+
+        assert_eq!(read_bytes(("127.0.0.1", port), b"/archive.zip").unwrap(), data);
+
+        let mut out = Vec::new();
+        match Client::builder().max_bytes(10000).build().download(&item, &mut out) {
+            Err(GopherError::ResponseTooLarge { limit: 10000 }) => assert!(out.len() <= 10000),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn progress_and_cancellation() {
+        use std::sync::Mutex;
+
+        let data = vec![b'x'; 20000];
+        let port = serve(vec![data.clone()]);
+        let item = DirectoryItem { t: Type::Binary, name: "Data".into(), selector: "/data".into(), host: "127.0.0.1".into(), port, extra: Vec::new() };
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let seen = reports.clone();
+        let client = Client::builder()
+            .progress(move |progress| seen.lock().unwrap().push(progress))
+            .build();
+        client.download(&item, &mut Vec::new()).unwrap();
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.last().unwrap().bytes, 20000);
+        assert!(reports.windows(2).all(|pair| pair[0].bytes <= pair[1].bytes));
+
+        // a server which never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        let client = Client::builder()
+            .cancellation(token)
+            .progress(move |progress| if progress.stalled { cancel.cancel() })
+            .build();
+        match client.read_bytes("127.0.0.1", port, b"/") {
+            Err(GopherError::Cancelled) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        drop(listener);
+
+        // a port with nothing listening, cancelled while waiting to retry
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let token = CancellationToken::new();
+        let client = Client::builder()
+            .cancellation(token.clone())
+            .retries(100)
+            .retry_delay(Duration::from_secs(1))
+            .build();
+        let cancel = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            token.cancel();
+        });
+        let start = Instant::now();
+        match client.read_bytes("127.0.0.1", port, b"/") {
+            Err(GopherError::Cancelled) => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+        cancel.join().unwrap();
+    }
+
+    #[test]
+    fn search_queries() {
+        // answer with the request, to check the query was sent after a tab
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+            let request = request.trim_end().replace('\t', "|");
+            write!(stream, "0{}\t/result.txt\tlocalhost\t70\r\n.\r\n", request).unwrap();
+        });
+
+        let item = DirectoryItem { t: Type::SearchServer, name: "Search".into(), selector: "/search".into(), host: "127.0.0.1".into(), port, extra: Vec::new() };
+        let client = Client::new();
+        let results = client.search(&item, "gopher clients").unwrap();
+        assert_eq!(results.items()[0].name, "/search|gopher clients");
+
+        for query in &["one\ttwo", "one\r\n", "one\ntwo"] {
+            match client.search(&item, query) {
+                Err(GopherError::InvalidQuery(ref q)) if q == query => {},
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+
+        let port = serve(vec![b"3No results\t\terror.host\t1\r\n.\r\n".to_vec()]);
+        let item = DirectoryItem { port, ..item };
+        match client.search(&item, "nothing") {
+            Err(GopherError::Server(ref message)) if message == "No results" => {},
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fetch_resources() {
+        let port = serve(vec![
+            b"A file\tcontaining tabs\tbut not a menu\r\n..dotted\r\n.\r\n".to_vec(),
+            b"3No such selector\t\terror.host\t1\r\n.\r\n".to_vec(),
+            b"GIF89a\x00\x01".to_vec(),
+            b"0Result\t/result.txt\tlocalhost\t70\r\n.\r\n".to_vec(),
+            b"Unknown\r\n".to_vec(),
+            b"1Caf\xe9s\t/caf\xe9s\tlocalhost\t70\r\n0No port\t/about.txt\tlocalhost\r\n.\r\n".to_vec(),
+        ]);
+        let client = Client::new();
+        let item = |t| DirectoryItem { t, name: "Item".into(), selector: "/".into(), host: "127.0.0.1".into(), port, extra: Vec::new() };
+
+        match client.fetch(&item(Type::File)).unwrap() {
+            Resource::Text(text) => assert_eq!(text.text(), "A file\tcontaining tabs\tbut not a menu\n.dotted\n"),
+            other => panic!("unexpected resource {:?}", other),
+        }
+        match client.fetch(&item(Type::Directory)).unwrap() {
+            Resource::Error(message) => assert_eq!(message, "No such selector"),
+            other => panic!("unexpected resource {:?}", other),
+        }
+        match client.fetch(&item(Type::GIF)).unwrap() {
+            Resource::Binary(bytes) => assert_eq!(bytes, b"GIF89a\x00\x01"),
+            other => panic!("unexpected resource {:?}", other),
+        }
+        match client.fetch(&item(Type::SearchServer)).unwrap() {
+            Resource::SearchResults(directory) => assert_eq!(directory.items().len(), 1),
+            other => panic!("unexpected resource {:?}", other),
+        }
+        match client.fetch(&item(Type::Unknown('z'))).unwrap() {
+            Resource::Text(text) => assert_eq!(text.text(), "Unknown\n"),
+            other => panic!("unexpected resource {:?}", other),
+        }
+        match client.fetch(&item(Type::Directory)).unwrap() {
+            Resource::Menu(directory) => {
+                assert_eq!(directory.items()[0].selector, "/caf\u{e9}s");
+                assert_eq!(directory.items()[1].port, 70);
+            },
+            other => panic!("unexpected resource {:?}", other),
+        }
+
+        match client.fetch(&item(Type::TelnetSession)).unwrap() {
+            Resource::ExternalUrl(url) => assert_eq!(url, format!("telnet://127.0.0.1:{}", port)),
+            other => panic!("unexpected resource {:?}", other),
+        }
     }
 }
@@ -0,0 +1,161 @@
+//! Link Normalization
+//!
+//! The same link is often spelled differently in different menus: the host
+//! in another case, as Unicode or punycode, or left empty to mean the server
+//! the menu came from.  `DirectoryItem`s compare, hash and sort by a
+//! normalized identity so that these are treated as the same link, for
+//! tracking visited links, bookmarks and so on.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+use idna;
+
+use Type;
+use DirectoryItem;
+
+/// Normalize a host name for comparison
+///
+/// Host names are lowercased, international domain names are converted to
+/// punycode, and any trailing `.` is removed.  Hosts which aren't valid
+/// domain names, such as IPv6 addresses, are just lowercased.
+///
+/// ```
+/// use gopher::*;
+///
+/// assert_eq!(normalize_host("Gopher.Example.NET."), "gopher.example.net");
+/// assert_eq!(normalize_host("gopher.Bücher.example"), "gopher.xn--bcher-kva.example");
+/// ```
+pub fn normalize_host(host: &str) -> String {
+    normalized_host(host).into_owned()
+}
+
+/// Normalize a host name, without copying it if it's already normalized
+///
+/// Most hosts are lowercase ASCII already, so comparing and hashing items
+/// doesn't need to allocate for them.
+pub(crate) fn normalized_host(host: &str) -> Cow<'_, str> {
+    let host = host.trim_end_matches('.');
+    if host.is_ascii() {
+        if host.bytes().any(|b| b.is_ascii_uppercase()) {
+            return Cow::Owned(host.to_ascii_lowercase());
+        }
+        return Cow::Borrowed(host);
+    }
+    Cow::Owned(idna::domain_to_ascii(host).unwrap_or_else(|_| host.to_lowercase()))
+}
+
+impl DirectoryItem {
+    /// Return a copy of this item with its link normalized
+    ///
+    /// The host is normalized with `normalize_host`, an empty host is
+    /// replaced with `parent_host`, the host of the menu the item came from,
+    /// and a port of 0 is replaced with the default of 70.  Info items are
+    /// returned unchanged.
+    ///
+    /// Items compare equal when their normalized types, selectors, hosts
+    /// and ports are the same, except that an empty host is only equal to
+    /// another empty host, since the parent isn't known.  Info items compare
+    /// by their text instead.
+    ///
+    /// ```
+    /// use gopher::*;
+    ///
+    /// let a = DirectoryItem::from_str("1Phlog\t/phlog\t\t0").unwrap();
+    /// let b = DirectoryItem::from_str("1My phlog\t/phlog\tGopher.Example.Net\t70").unwrap();
+    /// assert!(a != b);
+    /// assert!(a.normalize(Some("gopher.example.net")) == b);
+    /// ```
+    pub fn normalize(&self, parent_host: Option<&str>) -> DirectoryItem {
+        let mut item = self.clone();
+        if item.t == Type::Info {
+            return item;
+        }
+
+        item.host = match parent_host {
+            Some(parent) if item.host.is_empty() => normalize_host(parent),
+            _ => normalize_host(&item.host),
+        };
+        if item.port == 0 {
+            item.port = 70;
+        }
+        item
+    }
+
+    /// The normalized identity used for comparison, hashing and ordering
+    fn identity(&self) -> (Cow<'_, str>, u16, &str, Type, &str) {
+        if self.t == Type::Info {
+            (Cow::Borrowed(""), 0, "", Type::Info, &self.name)
+        } else {
+            let port = if self.port == 0 { 70 } else { self.port };
+            (normalized_host(&self.host), port, &self.selector, self.t, "")
+        }
+    }
+}
+
+impl PartialEq for DirectoryItem {
+    fn eq(&self, other: &DirectoryItem) -> bool {
+        self.identity() == other.identity()
+    }
+}
+
+impl Eq for DirectoryItem {}
+
+impl Hash for DirectoryItem {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.identity().hash(state)
+    }
+}
+
+impl PartialOrd for DirectoryItem {
+    fn partial_cmp(&self, other: &DirectoryItem) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Items are ordered by host, then port, selector and type
+impl Ord for DirectoryItem {
+    fn cmp(&self, other: &DirectoryItem) -> Ordering {
+        self.identity().cmp(&other.identity())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn normalized_identity() {
+        let items: Vec<DirectoryItem> = [
+            "1Phlog\t/phlog\tgopher.example.net\t70",
+            "1Phlog (again)\t/phlog\tGOPHER.example.net.\t70",
+            "1Phlog\t/phlog\tgopher.example.net\t0",
+            "1Phlog\t/PHLOG\tgopher.example.net\t70",
+            "0Phlog\t/phlog\tgopher.example.net\t70",
+            "1Phlog\t/phlog\tgopher.example.net\t7070",
+            "1Blog\t/phlog\tgopher.bücher.example\t70",
+            "1Blog\t/phlog\tgopher.xn--bcher-kva.example\t70",
+            "iSome text\t\t\t0",
+            "iSome text\tfake\t(NULL)\t0",
+            "iOther text\t\t\t0",
+        ].iter().map(|line| DirectoryItem::from_str(line).unwrap()).collect();
+
+        let unique: HashSet<_> = items.iter().cloned().collect();
+        assert_eq!(unique.len(), 7);
+        assert_eq!(items[0], items[1]);
+        assert_eq!(items[0], items[2]);
+        assert!(items[0] != items[3]);
+        assert!(items[0] != items[4]);
+        assert_eq!(items[6], items[7]);
+        assert_eq!(items[8], items[9]);
+
+        let mut sorted = items.clone();
+        sorted.sort();
+        assert_eq!(sorted[0].name, "Other text");
+        assert_eq!(sorted[3].host, "gopher.example.net");
+        assert_eq!(sorted[8].port, 7070);
+        assert_eq!(sorted.last().unwrap().host, "gopher.xn--bcher-kva.example");
+    }
+}
@@ -0,0 +1,139 @@
+//! Querying Directories
+//!
+//! Iterator adapters over the items of a Directory, and a model of the
+//! sections most gopherholes divide their menus into: a run of info lines
+//! acting as a heading, followed by the links under it.
+
+use regex::Regex;
+
+use GopherError;
+use Type;
+use DirectoryItem;
+use Directory;
+
+/// A group of links under the run of info lines heading it
+#[derive(Clone, Debug)]
+pub struct Section<'a> {
+    /// The info lines, which may be empty for links at the top of a menu
+    pub heading: Vec<&'a DirectoryItem>,
+    pub links: Vec<&'a DirectoryItem>,
+}
+
+impl<'a> Section<'a> {
+    /// The first non-blank line of the heading, trimmed
+    pub fn title(&self) -> Option<&'a str> {
+        self.heading.iter()
+            .map(|item| item.name.trim())
+            .find(|name| !name.is_empty())
+    }
+}
+
+impl Directory {
+    /// Iterate over the non-info link items
+    pub fn links(&self) -> impl Iterator<Item = &DirectoryItem> {
+        self.items.iter().filter(|item| !item.is_info())
+    }
+
+    /// Iterate over the links of the given type
+    pub fn links_of_type(&self, t: Type) -> impl Iterator<Item = &DirectoryItem> {
+        self.links().filter(move |item| item.t == t)
+    }
+
+    /// Iterate over the links to the given host, ignoring case
+    pub fn links_on_host<'a>(&'a self, host: &'a str) -> impl Iterator<Item = &'a DirectoryItem> {
+        self.links().filter(move |item| item.host.eq_ignore_ascii_case(host))
+    }
+
+    /// Iterate over the links with names matching a regular expression
+    ///
+    /// The pattern uses the syntax of the `regex` crate, so `(?i)` makes it
+    /// case insensitive.  Invalid patterns give `GopherError::InvalidPattern`.
+    pub fn links_matching<'a>(&'a self, pattern: &str) -> Result<impl Iterator<Item = &'a DirectoryItem>, GopherError> {
+        let name = Regex::new(pattern).map_err(|e| GopherError::InvalidPattern(e.to_string()))?;
+        Ok(self.links().filter(move |item| name.is_match(&item.name)))
+    }
+
+    /// Split the directory into sections, each starting at a run of info
+    /// lines
+    ///
+    /// ```
+    /// use gopher::*;
+    ///
+    /// let directory = DirectoryBuilder::new("gopher.example.net", 70)
+    ///     .link(Type::Directory, "Home", "/")
+    ///     .info("\nPhlog\n")
+    ///     .link(Type::File, "First post", "/phlog/first.txt")
+    ///     .link(Type::File, "Second post", "/phlog/second.txt")
+    ///     .info("Links")
+    ///     .link(Type::Directory, "Floodgap", "/")
+    ///     .try_build()
+    ///     .unwrap();
+    ///
+    /// let sections = directory.sections();
+    /// assert_eq!(sections.len(), 3);
+    /// assert_eq!(sections[0].title(), None);
+    /// assert_eq!(sections[1].title(), Some("Phlog"));
+    /// assert_eq!(sections[1].links.len(), 2);
+    /// ```
+    pub fn sections(&self) -> Vec<Section<'_>> {
+        let mut sections: Vec<Section> = Vec::new();
+        let mut in_heading = false;
+
+        for item in &self.items {
+            if item.is_info() {
+                if !in_heading {
+                    sections.push(Section { heading: Vec::new(), links: Vec::new() });
+                    in_heading = true;
+                }
+            } else {
+                if sections.is_empty() {
+                    sections.push(Section { heading: Vec::new(), links: Vec::new() });
+                }
+                in_heading = false;
+            }
+
+            let section = sections.last_mut().unwrap();
+            if item.is_info() {
+                section.heading.push(item);
+            } else {
+                section.links.push(item);
+            }
+        }
+
+        sections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_links() {
+        let directory = Directory::from_str("iWelcome\t\t\t0
+0About\t/about.txt\tgopher.example.net\t70
+1Phlog\t/phlog\tGopher.Example.Net\t70
+1Floodgap\t/\tgopher.floodgap.com\t70
+9Archive.zip\t/archive.zip\tgopher.example.net\t70
+iTrailing text\t\t\t0
+.").unwrap();
+
+        let names = |items: Vec<&DirectoryItem>| items.iter().map(|item| item.name.clone()).collect::<Vec<_>>();
+        assert_eq!(directory.links().count(), 4);
+        assert_eq!(names(directory.links_of_type(Type::Directory).collect()), vec!["Phlog", "Floodgap"]);
+        assert_eq!(names(directory.links_on_host("gopher.example.net").collect()), vec!["About", "Phlog", "Archive.zip"]);
+
+        assert_eq!(names(directory.links_matching(r"(?i)^a").unwrap().collect()), vec!["About", "Archive.zip"]);
+        match directory.links_matching("(unclosed") {
+            Err(GopherError::InvalidPattern(_)) => {},
+            Err(e) => panic!("unexpected error {:?}", e),
+            Ok(_) => panic!("invalid pattern accepted"),
+        }
+
+        let sections = directory.sections();
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].title(), Some("Welcome"));
+        assert_eq!(sections[0].links.len(), 4);
+        assert!(sections[1].links.is_empty());
+    }
+}
@@ -0,0 +1,319 @@
+//! Byte-Exact Directories
+//!
+//! Many older servers send menus in Latin-1, CP437 or Shift-JIS rather than
+//! UTF-8, which the `String`-based `Directory` can't represent.  A
+//! `RawDirectory` keeps every field as the exact bytes the server sent, so the
+//! selectors can be sent back unchanged, and can be decoded into a `Directory`
+//! for display once the character set is known.
+
+use std::fmt;
+
+use encoding_rs::SHIFT_JIS;
+
+use GopherError;
+use Position;
+use Type;
+use DirectoryItem;
+use Directory;
+
+/// Character sets commonly found in gopherspace
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Charset {
+    Utf8,
+    /// ISO-8859-1
+    Latin1,
+    /// The original IBM PC character set, with box-drawing characters
+    Cp437,
+    ShiftJis,
+}
+
+impl Charset {
+    /// Guess the character set of some text
+    ///
+    /// This is a simple heuristic: valid UTF-8 is assumed to be UTF-8, then
+    /// text which decodes cleanly as Shift-JIS is assumed to be Shift-JIS if
+    /// most of its high bytes are in double-byte characters which look like
+    /// kanji rather than an accented letter followed by ASCII.  Of the rest,
+    /// text using the `0x80`-`0x9F` range (accented letters in CP437, but
+    /// unused control codes in Latin-1) or mostly box-drawing characters is
+    /// assumed to be CP437, and anything else Latin-1.
+    pub fn detect(bytes: &[u8]) -> Charset {
+        if ::std::str::from_utf8(bytes).is_ok() {
+            return Charset::Utf8;
+        }
+
+        if is_shift_jis(bytes) {
+            return Charset::ShiftJis;
+        }
+
+        let high: Vec<u8> = bytes.iter().cloned().filter(|&b| b >= 0x80).collect();
+        let c1 = high.iter().filter(|&&b| b < 0xA0).count();
+        let box_drawing = high.iter().filter(|&&b| (0xB0..=0xDF).contains(&b)).count();
+        if c1 > 0 || box_drawing * 2 > high.len() {
+            Charset::Cp437
+        } else {
+            Charset::Latin1
+        }
+    }
+
+    /// Decode some text, replacing anything invalid with U+FFFD
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        match *self {
+            Charset::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Charset::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+            Charset::Cp437 => bytes.iter().map(|&b| {
+                if b < 0x80 { b as char } else { CP437_HIGH[(b - 0x80) as usize] }
+            }).collect(),
+            Charset::ShiftJis => SHIFT_JIS.decode_without_bom_handling(bytes).0.into_owned(),
+        }
+    }
+
+    /// The name of this character set, as used in MIME types
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Charset::Utf8 => "UTF-8",
+            Charset::Latin1 => "ISO-8859-1",
+            Charset::Cp437 => "IBM437",
+            Charset::ShiftJis => "Shift_JIS",
+        }
+    }
+}
+
+impl fmt::Display for Charset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Check for text which is valid Shift-JIS, and looks like it
+///
+/// A lead byte followed by ASCII is also a Latin-1 letter followed by ASCII,
+/// as in `caf\xe9s`, so only pairs with a high trail byte count in favour of
+/// Shift-JIS.  There must be several of them, outnumbering the ambiguous
+/// pairs, and between them the pairs must cover most of the high bytes.
+fn is_shift_jis(bytes: &[u8]) -> bool {
+    let mut strong = 0;
+    let mut weak = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let lead = bytes[i];
+        if (0x81..=0x9F).contains(&lead) || (0xE0..=0xFC).contains(&lead) {
+            match bytes.get(i + 1) {
+                Some(&trail) if (0x80..=0xFC).contains(&trail) => strong += 1,
+                Some(&trail) if (0x40..=0x7E).contains(&trail) => weak += 1,
+                _ => return false,
+            }
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+
+    let high = bytes.iter().filter(|&&b| b >= 0x80).count();
+    strong >= 2 && strong >= weak && (strong * 2 + weak) * 4 >= high * 3
+        && SHIFT_JIS.decode_without_bom_handling_and_without_replacement(bytes).is_some()
+}
+
+/// The characters for bytes `0x80` to `0xFF` in CP437
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// An item in a Gopher Directory, with the name and selector kept as the
+/// exact bytes sent by the server
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RawDirectoryItem {
+    pub t: Type,
+    pub name: Vec<u8>,
+    pub selector: Vec<u8>,
+    pub host: String,
+    pub port: u16,
+    pub extra: Vec<Vec<u8>>,
+}
+
+impl RawDirectoryItem {
+    /// Parse a line of bytes into a RawDirectoryItem
+    ///
+    /// As with `DirectoryItem::from_str`, errors are reported as if the item
+    /// was on line 1, and columns count bytes rather than characters.
+    pub fn from_bytes(line: &[u8]) -> Result<RawDirectoryItem, GopherError> {
+        let t = match line.first() {
+            Some(&b) if b.is_ascii_control() => {
+                return Err(GopherError::InvalidType { position: Position::new(1, 1), found: b as char });
+            },
+            Some(&b) => Type::from_char(b as char),
+            None => {
+                return Err(GopherError::MissingField { position: Position::new(1, 1), field: "type" });
+            },
+        };
+
+        let mut fields = line[1..].split(|&b| b == b'\t');
+        let mut column = 2;
+        let mut next_field = |name: &'static str| match fields.next() {
+            Some(field) => {
+                let start = column;
+                column += field.len() + 1;
+                Ok((field, start))
+            },
+            None => Err(GopherError::MissingField { position: Position::new(1, line.len() + 1), field: name }),
+        };
+
+        let (name, _) = next_field("name")?;
+        let (selector, _) = next_field("selector")?;
+        let (host, _) = next_field("host")?;
+        let (port, port_column) = next_field("port")?;
+
+        let port_text = String::from_utf8_lossy(port);
+        let port = match port_text.trim() {
+            "" => 70,
+            text => text.parse().map_err(|_| GopherError::BadPort {
+                position: Position::new(1, port_column),
+                port: text.into(),
+            })?,
+        };
+
+        Ok(RawDirectoryItem {
+            t,
+            name: name.to_vec(),
+            selector: selector.to_vec(),
+            host: String::from_utf8_lossy(host).into_owned(),
+            port,
+            extra: fields.map(|field| field.to_vec()).collect(),
+        })
+    }
+
+    /// Decode the name, selector and any extra fields into a DirectoryItem
+    ///
+    /// Note that the decoded selector may not give the same bytes when sent
+    /// back to the server, so links should be followed using the raw item.
+    pub fn decode(&self, charset: Charset) -> DirectoryItem {
+        DirectoryItem {
+            t: self.t,
+            name: charset.decode(&self.name),
+            selector: charset.decode(&self.selector),
+            host: self.host.clone(),
+            port: self.port,
+            extra: self.extra.iter().map(|field| charset.decode(field)).collect(),
+        }
+    }
+}
+
+/// A Gopher Directory of RawDirectoryItems
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RawDirectory {
+    items: Vec<RawDirectoryItem>,
+}
+
+impl RawDirectory {
+    /// Parse bytes into a RawDirectory, stopping at the `.` terminator
+    ///
+    /// Blank lines are skipped, so an empty response is an empty directory.
+    pub fn from_bytes(bytes: &[u8]) -> Result<RawDirectory, GopherError> {
+        let mut items = Vec::new();
+        for (n, line) in bytes.split(|&b| b == b'\n').enumerate() {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.trim_ascii() == b"." {
+                break;
+            }
+            if line.trim_ascii().is_empty() {
+                continue;
+            }
+            items.push(RawDirectoryItem::from_bytes(line).map_err(|e| e.at_line(n + 1))?);
+        }
+        Ok(RawDirectory { items })
+    }
+
+    /// Returns the list of all RawDirectoryItems, including info items
+    pub fn items(&self) -> &[RawDirectoryItem] {
+        &self.items
+    }
+
+    /// Guess the character set of the item names and selectors
+    pub fn detect_charset(&self) -> Charset {
+        let mut text = Vec::new();
+        for item in &self.items {
+            text.extend_from_slice(&item.name);
+            text.push(b'\n');
+            text.extend_from_slice(&item.selector);
+            text.push(b'\n');
+        }
+        Charset::detect(&text)
+    }
+
+    /// Decode into a Directory
+    pub fn decode(&self, charset: Charset) -> Directory {
+        Directory {
+            items: self.items.iter().map(|item| item.decode(charset)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_charsets() {
+        assert_eq!(Charset::detect(b"plain ascii"), Charset::Utf8);
+        assert_eq!(Charset::detect("caf\u{e9}".as_bytes()), Charset::Utf8);
+        assert_eq!(Charset::detect(b"caf\xe9 cr\xe8me br\xfbl\xe9e"), Charset::Latin1);
+        assert_eq!(Charset::detect(b"\xc9\xcd\xcd\xcd\xbb\n\xba Hi \xba\n\xc8\xcd\xcd\xcd\xbc"), Charset::Cp437);
+        assert_eq!(Charset::detect(b"caf\x82"), Charset::Cp437);
+        assert_eq!(Charset::detect(b"\x93\xfa\x96\x7b\x8c\xea"), Charset::ShiftJis);
+        assert_eq!(Charset::detect(b"Les \xe9tudiants"), Charset::Latin1);
+        assert_eq!(Charset::detect(b"Caf\xe9s"), Charset::Latin1);
+
+        assert_eq!(Charset::Latin1.decode(b"caf\xe9"), "café");
+        assert_eq!(Charset::Cp437.decode(b"\xc9\xcd\xbb caf\x82"), "╔═╗ café");
+        assert_eq!(Charset::ShiftJis.decode(b"\x93\xfa\x96\x7b\x8c\xea"), "日本語");
+    }
+
+    #[test]
+    fn parse_raw_directory() {
+        let input: &[u8] = b"iBienvenue au caf\xe9\tfake\t(NULL)\t0\r\n\
+                             0Le menu\t/caf\xe9/menu.txt\tgopher.example.net\t70\t+\r\n\
+                             .\r\n";
+        let directory = RawDirectory::from_bytes(input).expect("failed to parse raw directory");
+        assert_eq!(directory.items().len(), 2);
+
+        let item = &directory.items()[1];
+        assert_eq!(item.selector, b"/caf\xe9/menu.txt");
+        assert_eq!(item.host, "gopher.example.net");
+        assert_eq!(item.extra, vec![b"+".to_vec()]);
+
+        let charset = directory.detect_charset();
+        assert_eq!(charset, Charset::Latin1);
+
+        let decoded = directory.decode(charset);
+        assert_eq!(decoded.items()[0].name, "Bienvenue au café");
+        assert_eq!(decoded.items()[1].selector, "/café/menu.txt");
+        assert!(decoded.items()[1].is_gopher_plus());
+    }
+
+    #[test]
+    fn parse_raw_blank_lines() {
+        assert!(RawDirectory::from_bytes(b"").unwrap().items().is_empty());
+        assert!(RawDirectory::from_bytes(b"\r\n").unwrap().items().is_empty());
+
+        let input: &[u8] = b"0Le menu\t/menu.txt\tgopher.example.net\t70\r\n\r\n1Caf\xe9\t/\tgopher.example.net\t70\r\n";
+        let directory = RawDirectory::from_bytes(input).expect("failed to parse raw directory");
+        assert_eq!(directory.items().len(), 2);
+        assert_eq!(directory.items()[1].name, b"Caf\xe9");
+    }
+
+    #[test]
+    fn parse_raw_errors() {
+        let input: &[u8] = b"0Le menu\t/menu.txt\tgopher.example.net\t70\n1Bad\t/\tgopher.example.net\tx\xe9\n";
+        match RawDirectory::from_bytes(input) {
+            Err(GopherError::BadPort { position, .. }) => assert_eq!(position, Position::new(2, 27)),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Incremental Directory Parsing
+//!
+//! `Directory::from_str` needs the whole menu in memory before it can parse
+//! anything.  A `DirectoryReader` instead parses items one line at a time from
+//! any `BufRead`, so large menus can be processed as they arrive from the
+//! network.
+
+use std::io::prelude::*;
+use std::str;
+
+use GopherError;
+use DirectoryItem;
+use lenient::{self, Diagnostic, DiagnosticKind};
+
+/// An iterator over the items of a directory, read from a `BufRead`
+///
+/// Iteration stops at the `.` terminator line, or at the end of the input if
+/// the server didn't send one.  Both `\n` and `\r\n` line endings are accepted.
+///
+/// ```
+/// use gopher::*;
+///
+/// let input = "0About internet Gopher\tStuff:About us\trawBits.micro.umn.edu\t70\r
+/// 1Around University of Minnesota\tZ,5692,AUM\tunderdog.micro.umn.edu\t70\r
+/// .\r
+/// ";
+///
+/// let mut reader = DirectoryReader::new(input.as_bytes());
+/// let first = reader.next().unwrap().expect("failed to parse item");
+/// assert_eq!(first.selector, "Stuff:About us");
+/// assert_eq!(reader.line_number(), 1);
+/// assert_eq!(reader.count(), 1);
+/// ```
+///
+/// In lenient mode, malformed lines are recovered as well as possible instead
+/// of being returned as errors, and the problems found are collected as
+/// `Diagnostic`s.  Lines which aren't valid UTF-8 are decoded lossily.
+pub struct DirectoryReader<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    line_number: usize,
+    done: bool,
+    lenient: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<R: BufRead> DirectoryReader<R> {
+    /// Create a new reader
+    pub fn new(reader: R) -> DirectoryReader<R> {
+        DirectoryReader {
+            reader,
+            buffer: Vec::new(),
+            line_number: 0,
+            done: false,
+            lenient: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Switch to lenient parsing
+    pub fn lenient(mut self) -> DirectoryReader<R> {
+        self.lenient = true;
+        self
+    }
+
+    /// The problems recovered from so far, in lenient mode
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Take the diagnostics collected so far, leaving an empty list
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        ::std::mem::take(&mut self.diagnostics)
+    }
+
+    /// The number of the last line read, starting from 1
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// Unwrap this reader, returning the underlying `BufRead`
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Read the next line, without the line ending
+    /// Returns Ok(None) at the end of the input
+    fn read_line(&mut self) -> Result<Option<&str>, GopherError> {
+        self.buffer.clear();
+        if self.reader.read_until(b'\n', &mut self.buffer)? == 0 {
+            return Ok(None);
+        }
+        self.line_number += 1;
+
+        if self.buffer.ends_with(b"\n") { self.buffer.pop(); }
+        if self.buffer.ends_with(b"\r") { self.buffer.pop(); }
+
+        let line_number = self.line_number;
+        if self.lenient && str::from_utf8(&self.buffer).is_err() {
+            self.buffer = String::from_utf8_lossy(&self.buffer).into_owned().into_bytes();
+            self.diagnostics.push(Diagnostic { line: line_number, kind: DiagnosticKind::InvalidUtf8 });
+        }
+
+        let buffer = &self.buffer;
+        str::from_utf8(buffer)
+            .map(Some)
+            .map_err(|e| GopherError::non_utf8(buffer, e).at_line(line_number))
+    }
+}
+
+impl<R: BufRead> Iterator for DirectoryReader<R> {
+    type Item = Result<DirectoryItem, GopherError>;
+
+    fn next(&mut self) -> Option<Result<DirectoryItem, GopherError>> {
+        if self.done {
+            return None;
+        }
+
+        loop {
+            let lenient = self.lenient;
+            let line_number = self.line_number + 1;
+            let mut diagnostics = Vec::new();
+
+            let result = match self.read_line() {
+                Ok(Some(line)) if line.trim() == "." => None,
+                Ok(Some(line)) if lenient => {
+                    match lenient::parse_line(line, line_number, &mut diagnostics) {
+                        Some(item) => Some(Ok(item)),
+                        None => {
+                            self.diagnostics.append(&mut diagnostics);
+                            continue;
+                        }
+                    }
+                },
+                Ok(Some(line)) => Some(DirectoryItem::from_str(line).map_err(|e| e.at_line(line_number))),
+                Ok(None) => None,
+                Err(e) => Some(Err(e)),
+            };
+            self.diagnostics.append(&mut diagnostics);
+
+            match result {
+                Some(Ok(_)) => {},
+                _ => self.done = true,
+            }
+            return result;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Type;
+    use Position;
+
+    #[test]
+    fn read_directory() {
+        let input = "iWelcome\tfake\t(NULL)\t0\r\n\
+                     0About internet Gopher\tStuff:About us\trawBits.micro.umn.edu\t70\r\n\
+                     1Courses, Schedules, Calendars\t\tevents.ais.umn.edu\t9120\n\
+                     .\r\n\
+                     this should never be read\r\n";
+        let mut reader = DirectoryReader::new(input.as_bytes());
+
+        let info = reader.next().unwrap().expect("failed to read info item");
+        assert_eq!(info.t, Type::Info);
+        assert_eq!(reader.line_number(), 1);
+
+        let file = reader.next().unwrap().expect("failed to read file item");
+        assert_eq!(file.port, 70);
+
+        let dir = reader.next().unwrap().expect("failed to read directory item");
+        assert_eq!(dir.host, "events.ais.umn.edu");
+        assert_eq!(dir.port, 9120);
+        assert_eq!(reader.line_number(), 3);
+
+        assert!(reader.next().is_none());
+        assert_eq!(reader.line_number(), 4);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn read_unterminated_directory() {
+        let input = "0About internet Gopher\tStuff:About us\trawBits.micro.umn.edu\t70";
+        let items: Vec<_> = DirectoryReader::new(input.as_bytes()).collect();
+        assert_eq!(items.len(), 1);
+        assert!(items[0].is_ok());
+    }
+
+    #[test]
+    fn read_invalid_line() {
+        let input = "0About internet Gopher\tStuff:About us\trawBits.micro.umn.edu\t70\n\
+                     not a directory item\n\
+                     0About internet Gopher\tStuff:About us\trawBits.micro.umn.edu\t70\n";
+        let mut reader = DirectoryReader::new(input.as_bytes());
+        assert!(reader.next().unwrap().is_ok());
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(err.position(), Some(Position::new(2, 21)));
+        assert_eq!(reader.line_number(), 2);
+        assert!(reader.next().is_none());
+
+        let input: &[u8] = b"iWelcome\tfake\t(NULL)\t0\n0Caf\xe9\t/cafe\tgopher.example.net\t70\n";
+        let mut reader = DirectoryReader::new(input);
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next() {
+            Some(Err(GopherError::NonUtf8 { position, .. })) => assert_eq!(position, Position::new(2, 5)),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! Rendering for the Web and Geminispace
+//!
+//! Turns Directories and plain text resources into HTML, Gemtext or Markdown,
+//! for publishing mirrors of a gopherhole.  Runs of info lines are kept
+//! preformatted so ASCII art survives, and links point at `gopher://` URLs, or
+//! at the real URL for `URL:` items with a scheme in `LINK_SCHEMES`.
+//!
+//! ```
+//! use gopher::*;
+//! use gopher::render::{self, Format};
+//!
+//! let directory = DirectoryBuilder::new("gopher.example.net", 70)
+//!     .info("Welcome!")
+//!     .link(Type::File, "About", "/about.txt")
+//!     .try_build()
+//!     .unwrap();
+//!
+//! assert_eq!(render::directory(&directory, Format::Html), "<pre>Welcome!</pre>
+//! <p><a href=\"gopher://gopher.example.net/0/about.txt\">About</a></p>
+//! ");
+//! ```
+
+use Type;
+use DirectoryItem;
+use Directory;
+
+/// The schemes `URL:` items may link to directly
+///
+/// Anything else, such as `javascript:`, could run in a reader's browser, so
+/// those items link to their `gopher://` URL instead.
+pub const LINK_SCHEMES: &[&str] = &["http", "https", "gopher", "gemini", "mailto", "ftp"];
+
+/// The formats which can be rendered to
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Html,
+    /// The `text/gemini` format used in Geminispace
+    Gemtext,
+    /// CommonMark
+    Markdown,
+}
+
+/// Rendering settings beyond the format
+///
+/// Browsers can't send Gopher search queries themselves, so search items are
+/// rendered as plain links unless an HTML form action is given, such as an
+/// HTTP gateway which forwards the query.  The form sends the search item's
+/// `gopher://` URL as the `url` parameter and the query as `q`.
+///
+/// ```
+/// use gopher::*;
+/// use gopher::render::{Format, Renderer};
+///
+/// let directory = DirectoryBuilder::new("gopher.example.net", 70)
+///     .search("Search", "/search")
+///     .try_build()
+///     .unwrap();
+///
+/// let html = Renderer::new(Format::Html)
+///     .search_action("https://proxy.example.net/search")
+///     .directory(&directory);
+/// assert!(html.starts_with("<form action=\"https://proxy.example.net/search\""));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Renderer {
+    format: Format,
+    search_action: Option<String>,
+}
+
+impl Renderer {
+    pub fn new(format: Format) -> Renderer {
+        Renderer { format, search_action: None }
+    }
+
+    /// Render search items in HTML as forms submitting to `action`
+    pub fn search_action(mut self, action: &str) -> Renderer {
+        self.search_action = Some(action.into());
+        self
+    }
+
+    /// Render a Directory
+    pub fn directory(&self, directory: &Directory) -> String {
+        let format = self.format;
+        let mut out = String::new();
+        let mut info = Vec::new();
+
+        for item in directory.items() {
+            if item.is_info() || item.t == Type::Error {
+                info.push(item.name.as_str());
+                continue;
+            }
+            if !info.is_empty() {
+                out.push_str(&preformatted(&info, format));
+                info.clear();
+            }
+            out.push_str(&self.link(item));
+        }
+        if !info.is_empty() {
+            out.push_str(&preformatted(&info, format));
+        }
+
+        out
+    }
+
+    /// Render a plain text resource, keeping its layout
+    pub fn text(&self, text: &str) -> String {
+        let lines: Vec<&str> = text.lines().collect();
+        preformatted(&lines, self.format)
+    }
+
+    /// Render a link to an item, ending in a newline
+    fn link(&self, item: &DirectoryItem) -> String {
+        let url = url(item);
+        match (self.format, &self.search_action) {
+            (Format::Html, Some(action)) if item.t == Type::SearchServer => format!(
+                "<form action=\"{}\" method=\"get\"><input type=\"hidden\" name=\"url\" value=\"{}\">\
+                 <label>{} <input type=\"search\" name=\"q\"></label></form>\n",
+                escape_html(action), escape_html(&url), escape_html(&item.name)),
+            (Format::Html, _) => format!("<p><a href=\"{}\">{}</a></p>\n", escape_html(&url), escape_html(&item.name)),
+            (Format::Gemtext, _) => format!("=> {} {}\n", url, item.name),
+            (Format::Markdown, _) => format!("- [{}](<{}>)\n", escape_markdown(&item.name), url.replace('<', "%3C").replace('>', "%3E")),
+        }
+    }
+}
+
+/// Render a Directory, with search items as plain links
+pub fn directory(directory: &Directory, format: Format) -> String {
+    Renderer::new(format).directory(directory)
+}
+
+/// Render a plain text resource, keeping its layout
+pub fn text(text: &str, format: Format) -> String {
+    Renderer::new(format).text(text)
+}
+
+/// The URL an item links to, with any whitespace percent-encoded so it
+/// can't end the URL early
+fn url(item: &DirectoryItem) -> String {
+    let url = match item.external_url() {
+        Some(url) if has_link_scheme(url) => url.into(),
+        _ => item.url().to_string(),
+    };
+
+    let mut out = String::with_capacity(url.len());
+    for c in url.chars() {
+        if c.is_whitespace() || c.is_control() {
+            let mut bytes = [0; 4];
+            for b in c.encode_utf8(&mut bytes).bytes() {
+                out.push_str(&format!("%{:02X}", b));
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Check whether a URL has one of the `LINK_SCHEMES`
+fn has_link_scheme(url: &str) -> bool {
+    match url.find(':') {
+        Some(end) => LINK_SCHEMES.iter().any(|scheme| scheme.eq_ignore_ascii_case(&url[..end])),
+        None => false,
+    }
+}
+
+/// Render lines of text as a preformatted block, ending in a newline
+fn preformatted(lines: &[&str], format: Format) -> String {
+    let mut out = String::new();
+    match format {
+        Format::Html => {
+            out.push_str("<pre>");
+            out.push_str(&escape_html(&lines.join("\n")));
+            out.push_str("</pre>\n");
+        },
+        Format::Gemtext => {
+            out.push_str("```\n");
+            for line in lines {
+                // a line starting with ``` would end the block early
+                if line.starts_with("```") {
+                    out.push(' ');
+                }
+                out.push_str(line);
+                out.push('\n');
+            }
+            out.push_str("```\n");
+        },
+        Format::Markdown => {
+            // a fence longer than any run of backticks in the text can't be
+            // ended early by it, and unlike an indented block it isn't taken
+            // as part of a list item before it
+            let longest = lines.iter()
+                .flat_map(|line| line.split(|c| c != '`'))
+                .map(str::len)
+                .max()
+                .unwrap_or(0);
+            let fence = "`".repeat(longest.max(2) + 1);
+            out.push('\n');
+            out.push_str(&fence);
+            out.push('\n');
+            for line in lines {
+                out.push_str(line);
+                out.push('\n');
+            }
+            out.push_str(&fence);
+            out.push_str("\n\n");
+        },
+    }
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn escape_markdown(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\`*_[]<>#!|".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DirectoryBuilder;
+
+    fn menu() -> Directory {
+        DirectoryBuilder::new("gopher.example.net", 7070)
+            .info("<Welcome>\n```")
+            .link(Type::Directory, "Files & things", "/files")
+            .search("Search", "/search")
+            .external_url("Web [site]", "https://example.net/")
+            .try_build()
+            .unwrap()
+    }
+
+    #[test]
+    fn render_html() {
+        assert_eq!(directory(&menu(), Format::Html), "<pre>&lt;Welcome&gt;
+```</pre>
+<p><a href=\"gopher://gopher.example.net:7070/1/files\">Files &amp; things</a></p>
+<p><a href=\"gopher://gopher.example.net:7070/7/search\">Search</a></p>
+<p><a href=\"https://example.net/\">Web [site]</a></p>
+");
+        assert_eq!(text("a < b\n", Format::Html), "<pre>a &lt; b</pre>\n");
+
+        let html = Renderer::new(Format::Html).search_action("/gateway?a=1&b=2").directory(&menu());
+        assert!(html.contains("<form action=\"/gateway?a=1&amp;b=2\" method=\"get\">\
+                               <input type=\"hidden\" name=\"url\" value=\"gopher://gopher.example.net:7070/7/search\">\
+                               <label>Search <input type=\"search\" name=\"q\"></label></form>\n"));
+    }
+
+    #[test]
+    fn render_gemtext() {
+        assert_eq!(directory(&menu(), Format::Gemtext), "```
+<Welcome>
+ ```
+```
+=> gopher://gopher.example.net:7070/1/files Files & things
+=> gopher://gopher.example.net:7070/7/search Search
+=> https://example.net/ Web [site]
+");
+    }
+
+    #[test]
+    fn render_markdown() {
+        assert_eq!(directory(&menu(), Format::Markdown), "
+````
+<Welcome>
+```
+````
+
+- [Files & things](<gopher://gopher.example.net:7070/1/files>)
+- [Search](<gopher://gopher.example.net:7070/7/search>)
+- [Web \\[site\\]](<https://example.net/>)
+");
+
+        // info between links must not be taken as part of the list item
+        let directory = DirectoryBuilder::new("gopher.example.net", 70)
+            .link(Type::File, "First", "/first")
+            .info("  ~~ Section ~~")
+            .link(Type::File, "Second", "/second")
+            .try_build()
+            .unwrap();
+        assert_eq!(super::directory(&directory, Format::Markdown), "- [First](<gopher://gopher.example.net/0/first>)
+
+```
+  ~~ Section ~~
+```
+
+- [Second](<gopher://gopher.example.net/0/second>)
+");
+    }
+
+    #[test]
+    fn render_unsafe_urls() {
+        let directory = DirectoryBuilder::new("gopher.example.net", 70)
+            .external_url("Script", "javascript:alert(1)")
+            .external_url("Spaced", "https://a.example/b c")
+            .try_build()
+            .unwrap();
+
+        let html = super::directory(&directory, Format::Html);
+        assert!(!html.contains("href=\"javascript:"));
+        assert!(html.contains("<a href=\"gopher://gopher.example.net/hURL:javascript:alert(1)\">Script</a>"));
+        assert!(html.contains("<a href=\"https://a.example/b%20c\">Spaced</a>"));
+
+        assert_eq!(super::directory(&directory, Format::Gemtext), "=> gopher://gopher.example.net/hURL:javascript:alert(1) Script
+=> https://a.example/b%20c Spaced
+");
+    }
+}
@@ -0,0 +1,79 @@
+//! Serde Support
+//!
+//! Enabled with the `serde` feature.  `Type` is serialized as its item
+//! character, so a `DirectoryItem` looks like
+//! `{"t":"0","name":"About","selector":"/about.txt","host":"gopher.example.net","port":70}`.
+
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use Type;
+
+impl Serialize for Type {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_char(self.as_char())
+    }
+}
+
+struct TypeVisitor;
+
+impl<'de> Visitor<'de> for TypeVisitor {
+    type Value = Type;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a single item type character")
+    }
+
+    fn visit_char<E: de::Error>(self, c: char) -> Result<Type, E> {
+        Ok(Type::from_char(c))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Type, E> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(Type::from_char(c)),
+            _ => Err(E::invalid_value(de::Unexpected::Str(s), &self)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Type {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Type, D::Error> {
+        deserializer.deserialize_char(TypeVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+
+    use Type;
+    use DirectoryItem;
+    use Directory;
+
+    #[test]
+    fn serialize_directory() {
+        let input = "0About internet Gopher\tStuff:About us\trawBits.micro.umn.edu\t70
+1Gopher+\t/plus\tgopher.example.net\t70\t+
+.";
+        let directory = Directory::from_str(input).unwrap();
+        let json = serde_json::to_string(&directory).unwrap();
+        assert_eq!(json, r#"{"items":[{"t":"0","name":"About internet Gopher","selector":"Stuff:About us","host":"rawBits.micro.umn.edu","port":70},{"t":"1","name":"Gopher+","selector":"/plus","host":"gopher.example.net","port":70,"extra":["+"]}]}"#);
+
+        let parsed: Directory = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.to_string(), input);
+    }
+
+    #[test]
+    fn deserialize_types() {
+        let item: DirectoryItem = serde_json::from_str(r#"{"t":"z","name":"Odd","selector":"/","host":"gopher.example.net","port":70}"#).unwrap();
+        assert_eq!(item.t, Type::Unknown('z'));
+        assert!(item.extra.is_empty());
+
+        assert_eq!(serde_json::from_str::<Type>(r#""I""#).unwrap(), Type::Image);
+        assert!(serde_json::from_str::<Type>(r#""01""#).is_err());
+        assert!(serde_json::from_str::<Type>(r#""""#).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+//! Text Documents
+//!
+//! RFC 1436 text files are sent with CRLF line endings and end with a line
+//! containing a single `.`; any line of the file starting with a `.` has it
+//! doubled, so it can't be mistaken for the terminator.
+
+use std::fmt;
+
+/// A plain text resource, with the transfer encoding removed
+///
+/// ```
+/// use gopher::*;
+///
+/// let document = TextDocument::decode("Hello\r\n..hidden\r\n.\r\n");
+/// assert_eq!(document.text(), "Hello\n.hidden\n");
+/// assert_eq!(document.encode(), "Hello\r\n..hidden\r\n.\r\n");
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TextDocument {
+    text: String,
+}
+
+impl TextDocument {
+    /// Create a document from text, with any line endings
+    pub fn new(text: &str) -> TextDocument {
+        let mut document = TextDocument { text: String::with_capacity(text.len()) };
+        for line in split_lines(text) {
+            document.push_line(line);
+        }
+        document
+    }
+
+    /// Decode a text resource as sent by a server
+    ///
+    /// This stops at the `.` terminator, removes the extra `.` from lines
+    /// starting with `..`, and converts CRLF and CR line endings to LF.  Servers
+    /// which don't send a terminator are tolerated, and the whole response is
+    /// taken as the text.
+    pub fn decode(s: &str) -> TextDocument {
+        let mut document = TextDocument { text: String::with_capacity(s.len()) };
+        for line in split_lines(s) {
+            if line == "." {
+                break;
+            }
+            document.push_line(line.strip_prefix('.').filter(|rest| rest.starts_with('.')).unwrap_or(line));
+        }
+        document
+    }
+
+    /// Encode for sending to a client, with CRLF line endings, doubled dots
+    /// and a terminator
+    pub fn encode(&self) -> String {
+        let mut out = String::with_capacity(self.text.len() + self.text.len() / 20 + 3);
+        for line in self.lines() {
+            if line.starts_with('.') {
+                out.push('.');
+            }
+            out.push_str(line);
+            out.push_str("\r\n");
+        }
+        out.push_str(".\r\n");
+        out
+    }
+
+    /// The text, with each line ending in LF
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The lines of the text, without line endings
+    pub fn lines(&self) -> ::std::str::Lines<'_> {
+        self.text.lines()
+    }
+
+    fn push_line(&mut self, line: &str) {
+        self.text.push_str(line);
+        self.text.push('\n');
+    }
+}
+
+impl fmt::Display for TextDocument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Split text on CRLF, LF or CR line endings, without a final empty line
+fn split_lines(s: &str) -> impl Iterator<Item = &str> {
+    let empty = s.is_empty();
+    let s = s.strip_suffix("\r\n")
+        .or_else(|| s.strip_suffix('\n'))
+        .or_else(|| s.strip_suffix('\r'))
+        .unwrap_or(s);
+    let mut lines = s.split('\n').flat_map(|line| {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        line.split('\r')
+    });
+    // an empty input has no lines, rather than one empty line
+    if empty {
+        lines.next();
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_text() {
+        let document = TextDocument::decode("First\r\n\r\n..dotted\r\n.not stuffed\rold mac\n.\r\nafter the end\r\n");
+        assert_eq!(document.text(), "First\n\n.dotted\n.not stuffed\nold mac\n");
+        assert_eq!(document.lines().count(), 5);
+
+        let document = TextDocument::decode("No terminator\nat all");
+        assert_eq!(document.text(), "No terminator\nat all\n");
+
+        assert_eq!(TextDocument::decode("").text(), "");
+        assert_eq!(TextDocument::decode(".\r\n").text(), "");
+    }
+
+    #[test]
+    fn encode_text() {
+        let document = TextDocument::new(".\n..\nplain\n");
+        assert_eq!(document.encode(), "..\r\n...\r\nplain\r\n.\r\n");
+        assert_eq!(TextDocument::decode(&document.encode()), document);
+        assert_eq!(TextDocument::new("").encode(), ".\r\n");
+    }
+}